@@ -0,0 +1,536 @@
+// audit.rs - `audit`: no_std leaks, RustSec advisories and dependency licenses
+
+use crate::error::ToolError;
+use crate::logging::progress_bar;
+use crate::{platform_crates, platform_runtime, AuditCommands, GlueConfig, MultiTargetTool};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{error, info};
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Advisory {
+    pub(crate) id: String,
+    pub(crate) package: String,
+    pub(crate) version: String,
+    title: String,
+    url: Option<String>,
+    patched: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AdvisoryReport {
+    pub(crate) scope: String,
+    pub(crate) target: Option<String>,
+    pub(crate) advisories: Vec<Advisory>,
+}
+
+const COPYLEFT_LICENSES: &[&str] = &[
+    "GPL", "AGPL", "LGPL", "MPL", "EPL", "EUPL", "CDDL", "OSL", "CC-BY-SA",
+];
+
+// Alternatives of an SPDX expression, each a list of license ids that all apply
+pub(crate) fn license_alternatives(expression: &str) -> Vec<Vec<String>> {
+    expression
+        .replace(['(', ')'], "")
+        .replace('/', " OR ")
+        .split(" OR ")
+        .map(|alternative| {
+            alternative
+                .split(" AND ")
+                .map(|id| id.split(" WITH ").next().unwrap_or(id).trim().to_string())
+                .filter(|id| !id.is_empty())
+                .collect()
+        })
+        .collect()
+}
+
+fn is_copyleft(id: &str) -> bool {
+    COPYLEFT_LICENSES
+        .iter()
+        .any(|family| id == *family || id.starts_with(&format!("{}-", family)))
+}
+
+fn license_matches(id: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => id.starts_with(prefix),
+        None => id == pattern,
+    }
+}
+
+impl MultiTargetTool {
+    pub(crate) fn handle_audit_command(&self, cmd: AuditCommands) -> Result<(), ToolError> {
+        match cmd {
+            AuditCommands::NoStd { platform } => self.audit_no_std(platform.as_deref()),
+            AuditCommands::Security { json, db } => self.audit_security(json, db.as_deref()),
+        }
+    }
+
+    fn audit_no_std(&self, platform: Option<&str>) -> Result<(), ToolError> {
+        info!("🔍 Auditing no_std compatibility");
+        let config = self.load_glue_config()?;
+
+        let mut shared = vec![config.core_package().to_string()];
+        shared.extend(self.driver_crates()?);
+
+        // Each group is audited with a single `cargo tree` invocation for its target
+        let mut groups: Vec<(Option<String>, Vec<String>)> = Vec::new();
+        match platform {
+            Some(name) => {
+                let platform = config
+                    .platforms
+                    .iter()
+                    .find(|p| p.name == name)
+                    .ok_or_else(|| {
+                        ToolError::Config(format!("Platform '{}' not found in glue.toml", name))
+                    })?;
+                let mut crates = shared;
+                crates.extend(platform_crates(platform));
+                groups.push((Some(platform.target.clone()), crates));
+            }
+            None => {
+                groups.push((None, shared));
+                for platform in &config.platforms {
+                    let is_embedded = platform_runtime(platform).is_embedded();
+                    if is_embedded {
+                        groups.push((Some(platform.target.clone()), platform_crates(platform)));
+                    }
+                }
+            }
+        }
+
+        let mut findings: Vec<(String, String)> = Vec::new();
+        for (target, crates) in &groups {
+            for finding in self.no_std_findings(target.as_deref(), crates)? {
+                if !findings.iter().any(|(what, _)| *what == finding.0) {
+                    findings.push(finding);
+                }
+            }
+        }
+
+        if findings.is_empty() {
+            info!("✅ No std dependencies found");
+            return Ok(());
+        }
+        for (what, path) in &findings {
+            error!("  ❌ {}", what);
+            info!("     {}", path);
+        }
+        Err(ToolError::Other(format!(
+            "no_std audit found {} issue(s)",
+            findings.len()
+        )))
+    }
+
+    // std leaks under the given crates, as (finding, dependency path) pairs
+    pub(crate) fn no_std_findings(
+        &self,
+        target: Option<&str>,
+        crates: &[String],
+    ) -> Result<Vec<(String, String)>, ToolError> {
+        let mut findings: Vec<(String, String)> = Vec::new();
+        let crate_roots = self.crate_roots(target)?;
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(&self.project_root)
+            .args(["tree", "-e", "normal,features"]);
+        cmd.args(["--prefix", "depth"]);
+        for name in crates {
+            cmd.args(["-p", name]);
+        }
+        if let Some(target) = target {
+            cmd.args(["--target", target]);
+        }
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(ToolError::Build(format!(
+                "cargo tree failed:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+
+        let mut stack: Vec<String> = Vec::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+            let Ok(depth) = line[..digits].parse::<usize>() else {
+                continue;
+            };
+            let node = line[digits..].trim_end_matches(" (*)");
+            let label = match node.find(" (") {
+                Some(pos) => &node[..pos],
+                None => node,
+            };
+            stack.truncate(depth);
+            stack.push(label.to_string());
+
+            let mut words = label.split_whitespace();
+            let (Some(package), Some(second)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let issue = if second == "feature" {
+                label
+                    .ends_with("feature \"std\"")
+                    .then_some("enables the std feature")
+            } else {
+                let key = format!("{} {}", package, second.trim_start_matches('v'));
+                match crate_roots.get(&key) {
+                    Some(Some(root)) if !fs::read_to_string(root)?.contains("no_std") => {
+                        Some("does not declare no_std")
+                    }
+                    _ => None,
+                }
+            };
+            if let Some(issue) = issue {
+                let finding = (format!("{} {}", package, issue), stack.join(" → "));
+                if !findings.iter().any(|(what, _)| *what == finding.0) {
+                    findings.push(finding);
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    // `cargo metadata` for the workspace, resolved for one target when given
+    pub(crate) fn cargo_metadata(
+        &self,
+        target: Option<&str>,
+    ) -> Result<serde_json::Value, ToolError> {
+        self.resolve_metadata(target, false)
+    }
+
+    // `cargo metadata`, with `locked` refusing to write or update Cargo.lock
+    fn resolve_metadata(
+        &self,
+        target: Option<&str>,
+        locked: bool,
+    ) -> Result<serde_json::Value, ToolError> {
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(&self.project_root)
+            .args(["metadata", "--format-version", "1"]);
+        if let Some(target) = target {
+            cmd.args(["--filter-platform", target]);
+        }
+        if locked {
+            cmd.arg("--locked");
+        }
+        let output = cmd.output()?;
+        if !output.status.success() {
+            return Err(ToolError::Build(format!(
+                "cargo metadata failed:\n{}",
+                String::from_utf8_lossy(&output.stderr)
+            )));
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    // "name version" of every package the given workspace crates ship with (dev-deps excluded)
+    pub(crate) fn reachable_packages(
+        metadata: &serde_json::Value,
+        roots: &[String],
+    ) -> std::collections::HashSet<String> {
+        let mut names = std::collections::HashMap::new();
+        for package in metadata["packages"].as_array().into_iter().flatten() {
+            names.insert(
+                package["id"].as_str().unwrap_or_default().to_string(),
+                (
+                    package["name"].as_str().unwrap_or_default().to_string(),
+                    package["version"].as_str().unwrap_or_default().to_string(),
+                ),
+            );
+        }
+        let nodes: std::collections::HashMap<&str, &serde_json::Value> = metadata["resolve"]
+            ["nodes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|node| Some((node["id"].as_str()?, node)))
+            .collect();
+
+        let mut pending: Vec<&str> = metadata["workspace_members"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_str())
+            .filter(|id| names.get(*id).is_some_and(|(name, _)| roots.contains(name)))
+            .collect();
+        let mut seen = std::collections::HashSet::new();
+        while let Some(id) = pending.pop() {
+            let Some((name, version)) = names.get(id) else {
+                continue;
+            };
+            if !seen.insert(format!("{} {}", name, version)) {
+                continue;
+            }
+            let deps = nodes.get(id).and_then(|node| node["deps"].as_array());
+            for dep in deps.into_iter().flatten() {
+                let shipped = dep["dep_kinds"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|kind| kind["kind"].as_str() != Some("dev"));
+                if let (true, Some(pkg)) = (shipped, dep["pkg"].as_str()) {
+                    pending.push(pkg);
+                }
+            }
+        }
+        seen
+    }
+
+    // Advisories in the existing Cargo.lock, checked against the RustSec database in-process. The
+    // lockfile is only read: auditing never resolves the workspace, so `glue validate` leaves
+    // Cargo.lock exactly as it found it
+    fn security_advisories(&self, db: Option<&Path>) -> Result<Vec<Advisory>, ToolError> {
+        let lock_path = self.project_root.join("Cargo.lock");
+        if !lock_path.exists() {
+            return Err(ToolError::Config(
+                "Cargo.lock is missing. Run `cargo generate-lockfile` to audit dependencies"
+                    .to_string(),
+            ));
+        }
+        let lockfile = rustsec::Lockfile::load(&lock_path)
+            .map_err(|e| ToolError::Config(format!("{}: {}", lock_path.display(), e)))?;
+
+        let database = match db {
+            Some(path) => rustsec::Database::open(path).map_err(|e| {
+                ToolError::Config(format!("Advisory database {}: {}", path.display(), e))
+            })?,
+            None => {
+                let _spinner = progress_bar(None, "Fetching the RustSec advisory database");
+                // The fetch makes blocking HTTP requests, which can't run on the async runtime
+                std::thread::spawn(rustsec::Database::fetch)
+                    .join()
+                    .map_err(|_| {
+                        ToolError::Other("Fetching the advisory database panicked".into())
+                    })?
+                    .map_err(|e| {
+                        ToolError::Network(format!(
+                            "Could not fetch the RustSec advisory database: {}",
+                            e
+                        ))
+                    })?
+            }
+        };
+
+        Ok(database
+            .vulnerabilities(&lockfile)
+            .into_iter()
+            .map(|vuln| Advisory {
+                id: vuln.advisory.id.to_string(),
+                package: vuln.package.name.to_string(),
+                version: vuln.package.version.to_string(),
+                title: vuln.advisory.title,
+                url: vuln.advisory.url.map(|url| url.to_string()),
+                patched: vuln
+                    .versions
+                    .patched()
+                    .iter()
+                    .map(|req| req.to_string())
+                    .collect(),
+            })
+            .collect())
+    }
+
+    // Attribute advisories to core-lib and to each platform that ships the affected package
+    pub(crate) fn scoped_advisories(
+        &self,
+        config: &GlueConfig,
+        db: Option<&Path>,
+    ) -> Result<Vec<AdvisoryReport>, ToolError> {
+        let advisories = self.security_advisories(db)?;
+
+        let core = config.core_package().to_string();
+        let mut scopes = vec![("core-lib".to_string(), None, vec![core])];
+        for platform in &config.platforms {
+            scopes.push((
+                platform.name.clone(),
+                Some(platform.target.clone()),
+                platform_crates(platform),
+            ));
+        }
+
+        let mut reports = Vec::new();
+        for (scope, target, roots) in scopes {
+            let affected = if advisories.is_empty() {
+                Vec::new()
+            } else {
+                let metadata = self.resolve_metadata(target.as_deref(), true)?;
+                let shipped = Self::reachable_packages(&metadata, &roots);
+                advisories
+                    .iter()
+                    .filter(|a| shipped.contains(&format!("{} {}", a.package, a.version)))
+                    .cloned()
+                    .collect()
+            };
+            reports.push(AdvisoryReport {
+                scope,
+                target,
+                advisories: affected,
+            });
+        }
+        Ok(reports)
+    }
+
+    fn audit_security(&self, json: bool, db: Option<&Path>) -> Result<(), ToolError> {
+        let config = self.load_glue_config()?;
+        if !json {
+            info!("🔒 Checking security advisories");
+        }
+        let reports = self.scoped_advisories(&config, db)?;
+        let total: usize = reports.iter().map(|r| r.advisories.len()).sum();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&reports)?);
+        } else {
+            for report in &reports {
+                if report.advisories.is_empty() {
+                    info!("  ✓ {}: no known vulnerabilities", report.scope);
+                    continue;
+                }
+                error!("  ❌ {}:", report.scope);
+                for advisory in &report.advisories {
+                    info!(
+                        "     {} {} {}: {}",
+                        advisory.id, advisory.package, advisory.version, advisory.title
+                    );
+                    if !advisory.patched.is_empty() {
+                        info!("       patched: {}", advisory.patched.join(", "));
+                    }
+                }
+            }
+        }
+
+        if total > 0 {
+            return Err(ToolError::Other(format!(
+                "{} vulnerable dependency path(s) found",
+                total
+            )));
+        }
+        if !json {
+            info!("✅ No known vulnerabilities");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn licenses(&self) -> Result<(), ToolError> {
+        info!("📜 Checking dependency licenses");
+        let config = self.load_glue_config()?;
+        let core = config.core_package().to_string();
+        let policy = config.license_policy.unwrap_or_default();
+
+        let mut scopes = vec![("core-lib".to_string(), None, vec![core])];
+        for platform in &config.platforms {
+            scopes.push((
+                platform.name.clone(),
+                Some(platform.target.clone()),
+                platform_crates(platform),
+            ));
+        }
+
+        let mut violations = 0;
+        for (scope, target, roots) in scopes {
+            let metadata = self.cargo_metadata(target.as_deref())?;
+            let shipped = Self::reachable_packages(&metadata, &roots);
+            let members: Vec<&str> = metadata["workspace_members"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|id| id.as_str())
+                .collect();
+
+            let mut summary: std::collections::BTreeMap<String, usize> = Default::default();
+            let mut flagged = Vec::new();
+            for package in metadata["packages"].as_array().into_iter().flatten() {
+                let id = package["id"].as_str().unwrap_or_default();
+                let name = package["name"].as_str().unwrap_or_default();
+                let version = package["version"].as_str().unwrap_or_default();
+                if members.contains(&id) || !shipped.contains(&format!("{} {}", name, version)) {
+                    continue;
+                }
+                let license = package["license"].as_str().unwrap_or("unknown").to_string();
+                *summary.entry(license.clone()).or_default() += 1;
+
+                let alternatives = license_alternatives(&license);
+                let denied = !policy.exceptions.iter().any(|e| e == name)
+                    && !policy.deny.is_empty()
+                    && alternatives.iter().all(|ids| {
+                        ids.iter()
+                            .any(|id| policy.deny.iter().any(|p| license_matches(id, p)))
+                    });
+                let copyleft = alternatives
+                    .iter()
+                    .all(|ids| ids.iter().any(|id| is_copyleft(id)));
+                if denied {
+                    violations += 1;
+                    flagged.push(format!(
+                        "❌ {} {}: {} (denied by license_policy)",
+                        name, version, license
+                    ));
+                } else if copyleft {
+                    flagged.push(format!("⚠️  {} {}: {} (copyleft)", name, version, license));
+                } else if license == "unknown" {
+                    flagged.push(format!("⚠️  {} {}: no license declared", name, version));
+                }
+            }
+
+            let count: usize = summary.values().sum();
+            match &target {
+                Some(target) => info!("  🔧 {} ({}): {} crates", scope, target, count),
+                None => info!("  🔧 {}: {} crates", scope, count),
+            }
+            for (license, count) in &summary {
+                info!("    {:>3}  {}", count, license);
+            }
+            for line in flagged {
+                info!("    {}", line);
+            }
+        }
+
+        if violations > 0 {
+            return Err(ToolError::Other(format!(
+                "{} dependency license(s) violate license_policy",
+                violations
+            )));
+        }
+        info!("✅ License check complete");
+        Ok(())
+    }
+
+    // Map "name version" to the source root of each package, or None for proc-macros
+    fn crate_roots(
+        &self,
+        target: Option<&str>,
+    ) -> Result<std::collections::HashMap<String, Option<PathBuf>>, ToolError> {
+        let metadata = self.cargo_metadata(target)?;
+        let mut roots = std::collections::HashMap::new();
+        for package in metadata["packages"].as_array().into_iter().flatten() {
+            let key = format!(
+                "{} {}",
+                package["name"].as_str().unwrap_or_default(),
+                package["version"].as_str().unwrap_or_default()
+            );
+            let targets = package["targets"].as_array().into_iter().flatten();
+            let kinds = |t: &serde_json::Value| {
+                t["kind"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|k| k.as_str())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            };
+            let mut root = None;
+            for t in targets {
+                let kinds = kinds(t);
+                if kinds.iter().any(|k| k == "proc-macro") {
+                    root = None;
+                    break;
+                }
+                if root.is_none() && kinds.iter().any(|k| k == "lib" || k == "bin") {
+                    root = t["src_path"].as_str().map(PathBuf::from);
+                }
+            }
+            roots.insert(key, root);
+        }
+        Ok(roots)
+    }
+}
@@ -0,0 +1,58 @@
+// error.rs - The error every command returns, and the exit status it maps to
+
+// Every way a command can fail. Scripts and CI tell the classes apart by exit status; errors
+// from std, the parsers and the HTTP client are kept as the source
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ToolError {
+    /// glue.toml, a platform, board or device that isn't there or can't be used (exit 2)
+    #[error("{0}")]
+    Config(String),
+    /// cargo, cross or the esp toolchain didn't produce the firmware (exit 3)
+    #[error("{0}")]
+    Build(String),
+    /// A crates.io, GitHub or other remote request failed (exit 4)
+    #[error("{0}")]
+    Network(String),
+    /// The probe, a serial port or the board itself (exit 5)
+    #[error("{0}")]
+    Hardware(String),
+    /// Everything built but host, Miri or on-target tests failed (exit 6)
+    #[error("{0}")]
+    Test(String),
+    /// Anything else (exit 1)
+    #[error("{0}")]
+    Other(String),
+    /// Reading or writing a file, or starting a process (exit 1)
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    /// glue.toml, a manifest or another TOML file that doesn't parse (exit 2)
+    #[error("{0}")]
+    Toml(#[from] toml::de::Error),
+    /// A TOML document that can't be written (exit 1)
+    #[error("{0}")]
+    TomlWrite(#[from] toml::ser::Error),
+    /// JSON that doesn't parse or can't be written (exit 2)
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+    /// YAML that doesn't parse or can't be written (exit 2)
+    #[error("{0}")]
+    Yaml(#[from] serde_norway::Error),
+    /// An HTTP request that failed (exit 4)
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+}
+
+impl ToolError {
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            ToolError::Config(_) | ToolError::Toml(_) | ToolError::Json(_) | ToolError::Yaml(_) => {
+                2
+            }
+            ToolError::Build(_) => 3,
+            ToolError::Network(_) | ToolError::Http(_) => 4,
+            ToolError::Hardware(_) => 5,
+            ToolError::Test(_) => 6,
+            ToolError::Other(_) | ToolError::Io(_) | ToolError::TomlWrite(_) => 1,
+        }
+    }
+}
@@ -0,0 +1,363 @@
+// hil.rs - `hil serial-test`: scripted frame exchanges with a board over a serial port
+
+use crate::error::ToolError;
+use crate::journal::{create_dirs, write_file};
+use crate::logging::run_logged;
+use crate::{
+    find_device, junit_xml, HilCommands, MultiTargetTool, TestCaseResult, TestOutcome, TestReport,
+    TestSuiteResult,
+};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tracing::{error, info};
+
+// Host-side script for `hil serial-test`: each test sends frames and waits for the replies
+#[derive(Debug, Deserialize)]
+struct HilScript {
+    #[serde(default)]
+    framing: HilFraming,
+    baud: Option<u32>,
+    // Default time to wait for each expected frame
+    #[serde(default = "default_hil_timeout_ms")]
+    timeout_ms: u64,
+    tests: Vec<HilTest>,
+}
+
+fn default_hil_timeout_ms() -> u64 {
+    1000
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HilFraming {
+    // Newline-terminated text, e.g. an AT-style command shell
+    #[default]
+    Line,
+    // 0-terminated COBS frames, as written by the postcard protocol crate
+    Cobs,
+}
+
+#[derive(Debug, Deserialize)]
+struct HilTest {
+    name: String,
+    steps: Vec<HilStep>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HilStep {
+    send: Option<String>,
+    send_hex: Option<String>,
+    // Frames that don't match (log output, other replies) are skipped until the timeout
+    expect: Option<String>,
+    expect_hex: Option<String>,
+    timeout_ms: Option<u64>,
+}
+
+// Raw mode at `baud` so the tty layer doesn't echo or translate bytes; plain files (captures,
+// stand-ins in tests) are left alone
+pub(crate) fn configure_serial_port(port: &str, baud: u32) -> Result<(), String> {
+    let metadata = fs::metadata(port).map_err(|e| format!("Could not open {}: {}", port, e))?;
+    if metadata.is_file() {
+        return Ok(());
+    }
+    let flag = if cfg!(target_os = "macos") {
+        "-f"
+    } else {
+        "-F"
+    };
+    let output = run_logged(
+        Command::new("stty").args([flag, port, &baud.to_string(), "raw", "-echo"]),
+        true,
+    )
+    .map_err(|e| format!("Failed to run stty: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("Could not configure {} for {} baud", port, baud));
+    }
+    Ok(())
+}
+
+// Reads block, so a thread forwards received bytes to callers that need timeouts
+pub(crate) fn spawn_port_reader(mut reader: fs::File) -> std::sync::mpsc::Receiver<Vec<u8>> {
+    use std::io::Read;
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut chunk = [0u8; 256];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) => std::thread::sleep(std::time::Duration::from_millis(10)),
+                Ok(n) => {
+                    if tx.send(chunk[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+    rx
+}
+
+fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    let digits: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    // Checked before slicing: a multi-byte character would put a slice off a char boundary
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not hex", hex));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("'{}' is not an even number of hex digits", hex));
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| format!("'{}' is not hex", hex))
+        })
+        .collect()
+}
+
+fn cobs_encode(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0];
+    let mut code_index = 0;
+    for &byte in data {
+        if byte != 0 {
+            out.push(byte);
+        }
+        if byte == 0 || out.len() - code_index == 0xff {
+            out[code_index] = (out.len() - code_index) as u8;
+            code_index = out.len();
+            out.push(0);
+        }
+    }
+    out[code_index] = (out.len() - code_index) as u8;
+    out.push(0);
+    out
+}
+
+// Decode one COBS frame without its 0 terminator
+fn cobs_decode(frame: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut index = 0;
+    while index < frame.len() {
+        let code = usize::from(frame[index]);
+        if code == 0 || index + code > frame.len() {
+            return None;
+        }
+        out.extend_from_slice(&frame[index + 1..index + code]);
+        index += code;
+        if code < 0xff && index < frame.len() {
+            out.push(0);
+        }
+    }
+    Some(out)
+}
+
+// Bytes put on the wire for one step's send
+fn hil_send_bytes(framing: HilFraming, step: &HilStep) -> Result<Option<Vec<u8>>, String> {
+    let payload = match (&step.send, &step.send_hex) {
+        (Some(_), Some(_)) => return Err("a step can't have both send and send_hex".to_string()),
+        (Some(text), None) => text.as_bytes().to_vec(),
+        (None, Some(hex)) => parse_hex_bytes(hex)?,
+        (None, None) => return Ok(None),
+    };
+    Ok(Some(match framing {
+        HilFraming::Line => [payload, b"\n".to_vec()].concat(),
+        HilFraming::Cobs => cobs_encode(&payload),
+    }))
+}
+
+// Split complete frames off the front of `buffer`
+fn take_hil_frames(framing: HilFraming, buffer: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let terminator = match framing {
+        HilFraming::Line => b'\n',
+        HilFraming::Cobs => 0,
+    };
+    let mut frames = Vec::new();
+    while let Some(end) = buffer.iter().position(|&b| b == terminator) {
+        let frame: Vec<u8> = buffer.drain(..=end).take(end).collect();
+        match framing {
+            HilFraming::Line => {
+                frames.push(frame.strip_suffix(b"\r").unwrap_or(&frame).to_vec());
+            }
+            // Undecodable frames are line noise; they can't match anything
+            HilFraming::Cobs => frames.extend(cobs_decode(&frame)),
+        }
+    }
+    frames
+}
+
+fn hil_frame_text(framing: HilFraming, frame: &[u8]) -> String {
+    match framing {
+        HilFraming::Line => format!("{:?}", String::from_utf8_lossy(frame)),
+        HilFraming::Cobs => frame.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+impl MultiTargetTool {
+    pub(crate) fn handle_hil_command(&self, cmd: HilCommands) -> Result<(), ToolError> {
+        match cmd {
+            HilCommands::SerialTest {
+                script,
+                device,
+                port,
+                baud,
+                report,
+            } => self.hil_serial_test(&script, device, port, baud, report),
+        }
+    }
+
+    // Run each test's steps in order against the port; the device is expected to be flashed and
+    // running already (e.g. with `deploy`)
+    fn hil_serial_test(
+        &self,
+        script_path: &Path,
+        device: Option<String>,
+        port: Option<String>,
+        baud: Option<u32>,
+        report: Option<TestReport>,
+    ) -> Result<(), ToolError> {
+        use std::io::Write;
+        use std::time::{Duration, Instant};
+
+        let script: HilScript = toml::from_str(&fs::read_to_string(script_path)?)
+            .map_err(|e| ToolError::Config(format!("{}: {}", script_path.display(), e)))?;
+        let port = match port {
+            Some(port) => port,
+            None => {
+                let config = self.load_glue_config()?;
+                let name = device.unwrap_or_default();
+                find_device(&config, &name)?.port.clone().ok_or_else(|| {
+                    ToolError::Config(format!("Device '{}' has no serial port in glue.toml", name))
+                })?
+            }
+        };
+        let baud = baud.or(script.baud).unwrap_or(115200);
+        info!(
+            "🔌 Running {} serial test(s) from {} on {} at {} baud",
+            script.tests.len(),
+            script_path.display(),
+            port,
+            baud
+        );
+
+        configure_serial_port(&port, baud).map_err(ToolError::Hardware)?;
+        let mut writer = fs::OpenOptions::new()
+            .append(true)
+            .open(&port)
+            .map_err(|e| ToolError::Hardware(format!("Could not open {}: {}", port, e)))?;
+        let rx = spawn_port_reader(fs::File::open(&port)?);
+
+        let mut buffer = Vec::new();
+        let mut pending: std::collections::VecDeque<Vec<u8>> = Default::default();
+        let mut results = Vec::new();
+        for test in &script.tests {
+            let mut failure = None;
+            for (index, step) in test.steps.iter().enumerate() {
+                let fail = |message: String| format!("step {}: {}", index + 1, message);
+                match hil_send_bytes(script.framing, step) {
+                    Ok(Some(bytes)) => writer.write_all(&bytes)?,
+                    Ok(None) => {}
+                    Err(e) => {
+                        failure = Some(fail(e));
+                        break;
+                    }
+                }
+                let expected = match (&step.expect, &step.expect_hex) {
+                    (Some(text), _) => text.as_bytes().to_vec(),
+                    (None, Some(hex)) => match parse_hex_bytes(hex) {
+                        Ok(bytes) => bytes,
+                        Err(e) => {
+                            failure = Some(fail(e));
+                            break;
+                        }
+                    },
+                    (None, None) => continue,
+                };
+
+                let timeout = Duration::from_millis(step.timeout_ms.unwrap_or(script.timeout_ms));
+                let deadline = Instant::now() + timeout;
+                let mut skipped = Vec::new();
+                let matched = loop {
+                    if let Some(frame) = pending.pop_front() {
+                        if frame == expected {
+                            break true;
+                        }
+                        skipped.push(hil_frame_text(script.framing, &frame));
+                        continue;
+                    }
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    match rx.recv_timeout(remaining) {
+                        Ok(bytes) => {
+                            buffer.extend_from_slice(&bytes);
+                            pending.extend(take_hil_frames(script.framing, &mut buffer));
+                        }
+                        Err(_) => break false,
+                    }
+                };
+                if !matched {
+                    failure = Some(fail(format!(
+                        "expected {} within {} ms; received [{}]",
+                        hil_frame_text(script.framing, &expected),
+                        timeout.as_millis(),
+                        skipped.join(", ")
+                    )));
+                    break;
+                }
+            }
+
+            info!(
+                "test {} ... {}",
+                test.name,
+                if failure.is_none() { "ok" } else { "FAILED" }
+            );
+            results.push(TestCaseResult {
+                name: test.name.clone(),
+                outcome: if failure.is_none() {
+                    TestOutcome::Passed
+                } else {
+                    TestOutcome::Failed
+                },
+                output: failure.unwrap_or_default(),
+            });
+        }
+
+        let failed: Vec<_> = results
+            .iter()
+            .filter(|r| r.outcome == TestOutcome::Failed)
+            .collect();
+        for result in &failed {
+            error!("  ❌ {}: {}", result.name, result.output);
+        }
+        if let Some(TestReport::Junit(path)) = &report {
+            let path = self.project_root.join(path);
+            if let Some(parent) = path.parent() {
+                create_dirs(parent)?;
+            }
+            let suite = script_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "hil".to_string());
+            let suites = [TestSuiteResult {
+                name: suite.clone(),
+                class: suite,
+                cases: results.clone(),
+            }];
+            write_file(&path, junit_xml(&suites))?;
+            info!(
+                "📝 Wrote JUnit report for {} test(s) to {}",
+                results.len(),
+                path.display()
+            );
+        }
+        if !failed.is_empty() {
+            return Err(ToolError::Test(format!(
+                "{} of {} serial tests failed",
+                failed.len(),
+                results.len()
+            )));
+        }
+        info!("✅ All {} serial tests passed", results.len());
+        Ok(())
+    }
+}
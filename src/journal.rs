@@ -0,0 +1,271 @@
+// journal.rs - File changes made by scaffolding commands, rolled back when a command fails
+
+use crate::error::ToolError;
+use crate::{unix_time, MultiTargetTool};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+// Where scaffolding commands keep the previous contents of files they changed
+const BACKUP_DIR: &str = ".multi-target-rs/backups";
+
+// Directories a walk of the project tree leaves alone: build output, vendored sources, history
+// and our own state
+const WALK_SKIP: &[&str] = &["target", "vendor", ".git", "artifacts", ".multi-target-rs"];
+
+// The paths a scaffolding command wrote, removed or created, with what was there before, so a
+// failure part way through can put exactly those back and a success can back up what it
+// overwrote. Only changes made through write_file, create_dirs, remove_file and remove_tree are
+// journaled; the rest of the tree, and files other processes write meanwhile, are never touched
+#[derive(Default)]
+pub(crate) struct Journal {
+    // Files in the order they were first touched, with their previous contents (None if new)
+    files: Vec<(PathBuf, Option<Vec<u8>>)>,
+    // Directories the command created, outermost only; rollback removes them whole
+    dirs: Vec<PathBuf>,
+}
+
+static JOURNAL: std::sync::Mutex<Option<Journal>> = std::sync::Mutex::new(None);
+
+impl Journal {
+    // Start journaling; false if a transaction is already running, which then owns the journal
+    pub(crate) fn start() -> bool {
+        let mut journal = JOURNAL.lock().unwrap_or_else(|e| e.into_inner());
+        if journal.is_some() {
+            return false;
+        }
+        *journal = Some(Journal::default());
+        true
+    }
+
+    fn take() -> Option<Journal> {
+        JOURNAL.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    // Remember a file's contents before its first change in the running transaction, if any
+    pub(crate) fn record_file(path: &Path) {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut journal = JOURNAL.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(journal) = journal.as_mut() else {
+            return;
+        };
+        let known = journal.files.iter().any(|(file, _)| *file == path)
+            || journal.dirs.iter().any(|dir| path.starts_with(dir));
+        if !known {
+            let previous = fs::read(&path).ok();
+            journal.files.push((path, previous));
+        }
+    }
+
+    // Remember the outermost directory that creating `path` brings into existence
+    pub(crate) fn record_dirs(path: &Path) {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let mut journal = JOURNAL.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(journal) = journal.as_mut() else {
+            return;
+        };
+        let Some(outermost) = path.ancestors().take_while(|dir| !dir.exists()).last() else {
+            return;
+        };
+        if !journal.dirs.iter().any(|dir| outermost.starts_with(dir)) {
+            journal.dirs.push(outermost.to_path_buf());
+        }
+    }
+
+    // Put back every journaled file and remove the directories the command created; returns the
+    // number of paths touched
+    fn rollback(&self) -> std::io::Result<usize> {
+        let mut restored = 0;
+        for (path, previous) in self.files.iter().rev() {
+            match previous {
+                Some(content) => {
+                    if fs::read(path).is_ok_and(|current| current == *content) {
+                        continue;
+                    }
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::write(path, content)?;
+                }
+                None if path.exists() => fs::remove_file(path)?,
+                None => continue,
+            }
+            restored += 1;
+        }
+        for dir in self.dirs.iter().rev().filter(|dir| dir.exists()) {
+            fs::remove_dir_all(dir)?;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    // Copy the old contents of changed files under BACKUP_DIR/<unix time>/; None if nothing changed
+    fn backup_changes(&self, root: &Path) -> std::io::Result<Option<PathBuf>> {
+        let root = std::path::absolute(root)?;
+        let relative = Path::new(BACKUP_DIR).join(unix_time().to_string());
+        let mut backed_up = false;
+        for (path, previous) in &self.files {
+            let (Some(content), Ok(file)) = (previous, path.strip_prefix(&root)) else {
+                continue;
+            };
+            if fs::read(path).is_ok_and(|current| current == *content) {
+                continue;
+            }
+            let backup = root.join(&relative).join(file);
+            if let Some(parent) = backup.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(backup, content)?;
+            backed_up = true;
+        }
+        Ok(backed_up.then_some(relative))
+    }
+}
+
+// Write a file whole: the contents go to a temporary sibling that is renamed over the target, so
+// an interrupted command never leaves a half-written file behind. Journaled in a transaction
+pub(crate) fn write_file(
+    path: impl AsRef<Path>,
+    contents: impl AsRef<[u8]>,
+) -> std::io::Result<()> {
+    replace_file(path.as_ref(), contents.as_ref(), false)
+}
+
+// write_file for a file only the user may read. The temporary file is created 0600 before
+// anything is written to it, so the contents are never readable by others
+pub(crate) fn write_private_file(
+    path: impl AsRef<Path>,
+    contents: impl AsRef<[u8]>,
+) -> std::io::Result<()> {
+    replace_file(path.as_ref(), contents.as_ref(), true)
+}
+
+fn replace_file(path: &Path, contents: &[u8], private: bool) -> std::io::Result<()> {
+    Journal::record_file(path);
+    let name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a file path", path.display()),
+        )
+    })?;
+    let mut partial = std::ffi::OsString::from(".");
+    partial.push(name);
+    partial.push(".partial");
+    let partial = path.with_file_name(partial);
+    if private {
+        use std::io::Write;
+        // A leftover temporary file would keep its old mode
+        let _ = fs::remove_file(&partial);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&partial)
+            .and_then(|mut file| file.write_all(contents))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&partial);
+            })?;
+    } else {
+        fs::write(&partial, contents)?;
+        // Keep e.g. the executable bit of a script being regenerated
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&partial, metadata.permissions())?;
+        }
+    }
+    fs::rename(&partial, path).inspect_err(|_| {
+        let _ = fs::remove_file(&partial);
+    })
+}
+
+// fs::create_dir_all, journaled in a transaction
+pub(crate) fn create_dirs(path: impl AsRef<Path>) -> std::io::Result<()> {
+    Journal::record_dirs(path.as_ref());
+    fs::create_dir_all(path)
+}
+
+// fs::remove_file, journaled in a transaction
+pub(crate) fn remove_file(path: impl AsRef<Path>) -> std::io::Result<()> {
+    Journal::record_file(path.as_ref());
+    fs::remove_file(path)
+}
+
+// fs::remove_dir_all, journaling every file in the tree so a rollback can bring them back
+pub(crate) fn remove_tree(path: impl AsRef<Path>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let (_, files) = walk_paths(path)?;
+    for file in files {
+        Journal::record_file(&path.join(file));
+    }
+    fs::remove_dir_all(path)
+}
+
+// Directories and files under `root`, relative to it, skipping WALK_SKIP
+pub(crate) fn walk_paths(
+    root: &Path,
+) -> std::io::Result<(
+    std::collections::BTreeSet<PathBuf>,
+    std::collections::BTreeSet<PathBuf>,
+)> {
+    let mut dirs = std::collections::BTreeSet::new();
+    let mut files = std::collections::BTreeSet::new();
+    let mut pending = vec![PathBuf::new()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(root.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if !WALK_SKIP.iter().any(|skip| entry.file_name() == *skip) {
+                    dirs.insert(relative.clone());
+                    pending.push(relative);
+                }
+            } else if file_type.is_file() {
+                files.insert(relative);
+            }
+        }
+    }
+    Ok((dirs, files))
+}
+
+impl MultiTargetTool {
+    // Run a scaffolding command so that it either completes or leaves the project as it was;
+    // files it overwrote are backed up under BACKUP_DIR. Commands that only build, test, flash or
+    // report run outside a transaction: their output under target/ and artifacts/, and the state
+    // store, record what happened even when the command fails
+    pub(crate) fn transaction<T>(
+        &self,
+        op: impl FnOnce(&Self) -> Result<T, ToolError>,
+    ) -> Result<T, ToolError> {
+        let started = Journal::start();
+        let result = op(self);
+        if started {
+            self.end_transaction(result.is_ok())?;
+        }
+        result
+    }
+
+    // Commit or roll back the journal of the transaction that Journal::start began
+    pub(crate) fn end_transaction(&self, succeeded: bool) -> std::io::Result<()> {
+        let Some(journal) = Journal::take() else {
+            return Ok(());
+        };
+        if succeeded {
+            if let Some(backup) = journal.backup_changes(&self.project_root)? {
+                info!(
+                    "  ℹ️  Previous versions of changed files are in {}",
+                    backup.display()
+                );
+            }
+        } else {
+            let restored = journal.rollback()?;
+            if restored > 0 {
+                info!("↩️  Rolled back {} generated or changed paths", restored);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,275 @@
+// logging.rs - Log output, tool output streaming and progress bars
+
+use std::process::Command;
+use tracing::{debug, info, warn};
+
+// Run a command with its output logged line by line: stdout under the `cmd` target, stderr under
+// `cmd::stderr` (which still goes to stderr), so -q, -v and MULTI_TARGET_LOG apply to cargo, cross
+// and probe-rs as well. Both are also returned, for callers that parse them; with `log_stdout`
+// false stdout is only captured, e.g. cargo's JSON messages
+pub(crate) fn run_logged(
+    cmd: &mut Command,
+    log_stdout: bool,
+) -> std::io::Result<std::process::Output> {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    debug!("Running {:?}", cmd);
+    // Piped output isn't a terminal, so cargo would never color it on its own
+    let color = if output_style().color {
+        "always"
+    } else {
+        "never"
+    };
+    let mut child = cmd
+        .env("CARGO_TERM_COLOR", color)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stderr = child.stderr.take().map(|pipe| {
+        std::thread::spawn(move || {
+            let mut reader = BufReader::new(pipe);
+            let mut stderr = Vec::new();
+            loop {
+                let start = stderr.len();
+                if !reader.read_until(b'\n', &mut stderr).is_ok_and(|n| n > 0) {
+                    break;
+                }
+                info!(target: "cmd::stderr", "{}", String::from_utf8_lossy(&stderr[start..]).trim_end());
+            }
+            stderr
+        })
+    });
+    let mut stdout = Vec::new();
+    if let Some(pipe) = child.stdout.take() {
+        let mut reader = BufReader::new(pipe);
+        loop {
+            let start = stdout.len();
+            if reader.read_until(b'\n', &mut stdout)? == 0 {
+                break;
+            }
+            if log_stdout {
+                info!(target: "cmd", "{}", String::from_utf8_lossy(&stdout[start..]).trim_end());
+            }
+        }
+    }
+    let status = child.wait()?;
+    let stderr = stderr
+        .and_then(|thread| thread.join().ok())
+        .unwrap_or_default();
+    // -q filtered out the streamed lines, but a failing tool's stderr is what says why
+    if !status.success() && !tracing::enabled!(target: "cmd::stderr", tracing::Level::INFO) {
+        for line in String::from_utf8_lossy(&stderr).lines() {
+            warn!(target: "cmd::stderr", "{}", line);
+        }
+    }
+    Ok(std::process::Output {
+        status,
+        stdout,
+        stderr,
+    })
+}
+
+// Progress bars and spinners, drawn on stderr. Log lines are written around them (see
+// `ProgressAwareWriter`) so they scroll by above the bars
+static PROGRESS: std::sync::LazyLock<indicatif::MultiProgress> =
+    std::sync::LazyLock::new(indicatif::MultiProgress::new);
+
+// A bar over `len` steps, or a spinner when the length isn't known. Hidden when stderr isn't a
+// terminal or -q turned info output off; the plain log lines around it say the same in CI logs
+pub(crate) fn progress_bar(len: Option<u64>, message: impl Into<String>) -> indicatif::ProgressBar {
+    use std::io::IsTerminal;
+    if !std::io::stderr().is_terminal() || !tracing::enabled!(tracing::Level::INFO) {
+        return indicatif::ProgressBar::hidden();
+    }
+    let (bar, template) = match len {
+        Some(len) => (
+            indicatif::ProgressBar::new(len),
+            "{spinner} {prefix}[{bar:30}] {pos}/{len} {msg}",
+        ),
+        None => (
+            indicatif::ProgressBar::new_spinner(),
+            "{spinner} {prefix}{msg}",
+        ),
+    };
+    let bar = PROGRESS.add(bar.with_finish(indicatif::ProgressFinish::AndClear));
+    if let Ok(mut style) = indicatif::ProgressStyle::with_template(template) {
+        if output_style().plain {
+            style = style.tick_chars("|/-\\ ");
+        }
+        bar.set_style(style.progress_chars("=> "));
+    }
+    bar.set_message(message.into());
+    bar.enable_steady_tick(std::time::Duration::from_millis(100));
+    bar
+}
+
+// Writes log output with the progress bars cleared for the moment, then redraws them
+struct ProgressAwareWriter<W>(W);
+
+impl<W: std::io::Write> std::io::Write for ProgressAwareWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        PROGRESS.suspend(|| self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+// The last percentage in a flasher's progress output, e.g. `Programming ... 42%`
+pub(crate) fn progress_percent(line: &str) -> Option<u64> {
+    line.split_whitespace()
+        .rev()
+        .filter_map(|word| word.strip_suffix('%')?.parse::<f64>().ok())
+        .map(|percent| percent.clamp(0.0, 100.0) as u64)
+        .next()
+}
+
+// How output is decorated, decided once at startup from --no-color, NO_COLOR and --plain
+#[derive(Clone, Copy, Default)]
+pub(crate) struct OutputStyle {
+    // ANSI colors: warnings yellow, errors red, debug dimmed, and cargo's own colors
+    color: bool,
+    // ASCII only, for serial consoles, CI logs and terminals with limited encodings
+    plain: bool,
+}
+
+pub(crate) static OUTPUT_STYLE: std::sync::OnceLock<OutputStyle> = std::sync::OnceLock::new();
+
+fn output_style() -> OutputStyle {
+    OUTPUT_STYLE.get().copied().unwrap_or_default()
+}
+
+impl OutputStyle {
+    pub(crate) fn new(no_color: bool, plain: bool) -> Self {
+        use std::io::IsTerminal;
+        // https://no-color.org: set and not empty
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        OutputStyle {
+            color: !no_color && !no_color_env && std::io::stdout().is_terminal(),
+            plain,
+        }
+    }
+}
+
+// Replace the emoji and arrows of a log line with ASCII: status emoji become tags, decorative
+// ones a `*`, and the padding after them one space
+fn plain_text(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let replacement = match ch {
+            '✅' => "[ok]",
+            '✓' => "+",
+            '❌' | '✗' => "[fail]",
+            '⚠' => "[warn]",
+            'ℹ' => "[info]",
+            '↩' => "[undo]",
+            '→' | '➡' => "->",
+            '\u{fe0f}' | '\u{200d}' => continue,
+            '\u{2190}'..='\u{23ff}'
+            | '\u{2600}'..='\u{27bf}'
+            | '\u{2b00}'..='\u{2bff}'
+            | '\u{1f000}'..='\u{1faff}' => "*",
+            _ => {
+                out.push(ch);
+                continue;
+            }
+        };
+        out.push_str(replacement);
+        while chars.peek() == Some(&'\u{fe0f}') {
+            chars.next();
+        }
+        if chars.peek() == Some(&' ') {
+            while chars.peek() == Some(&' ') {
+                chars.next();
+            }
+            out.push(' ');
+        }
+    }
+    out
+}
+
+// Log lines are the event's message alone; warnings, errors and debug output are colored when
+// the style allows, and emoji replaced in plain mode
+struct LineFormat(OutputStyle);
+
+impl<S, N> tracing_subscriber::fmt::FormatEvent<S, N> for LineFormat
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    N: for<'a> tracing_subscriber::fmt::FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &tracing_subscriber::fmt::FmtContext<'_, S, N>,
+        mut writer: tracing_subscriber::fmt::format::Writer<'_>,
+        event: &tracing::Event<'_>,
+    ) -> std::fmt::Result {
+        use tracing_subscriber::fmt::FormatFields;
+
+        let mut line = String::new();
+        ctx.format_fields(
+            tracing_subscriber::fmt::format::Writer::new(&mut line),
+            event,
+        )?;
+        if self.0.plain {
+            line = plain_text(&line);
+        }
+        let metadata = event.metadata();
+        // Tool output keeps its own colors
+        let color = match *metadata.level() {
+            _ if !self.0.color || metadata.target().starts_with("cmd") => None,
+            tracing::Level::ERROR => Some("31"),
+            tracing::Level::WARN => Some("33"),
+            tracing::Level::DEBUG | tracing::Level::TRACE => Some("2"),
+            tracing::Level::INFO => None,
+        };
+        match color {
+            Some(code) => writeln!(writer, "\x1b[{}m{}\x1b[0m", code, line),
+            None => writeln!(writer, "{}", line),
+        }
+    }
+}
+
+// The final error of a failed command, on stderr
+pub(crate) fn print_error(error: &dyn std::fmt::Display) {
+    use std::io::IsTerminal;
+    let style = output_style();
+    let mut message = format!("Error: {}", error);
+    if style.plain {
+        message = plain_text(&message);
+    }
+    if style.color && std::io::stderr().is_terminal() {
+        eprintln!("\x1b[31m{}\x1b[0m", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+// Send output through tracing without timestamps or levels, so it reads like plain lines. -q keeps
+// warnings and errors, -v adds debug events such as the commands being run, -vv everything; an
+// env-filter directive in MULTI_TARGET_LOG (e.g. "info,cmd=off") takes precedence. Warnings,
+// errors and the stderr of commands go to stderr, so piping stdout keeps only the regular output
+pub(crate) fn init_logging(verbose: u8, quiet: bool, style: OutputStyle) {
+    use tracing_subscriber::fmt::writer::MakeWriterExt;
+
+    let directive = match (quiet, verbose) {
+        (true, _) => "warn",
+        (false, 0) => "info",
+        (false, 1) => "info,multi_target_rs=debug",
+        (false, _) => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_env("MULTI_TARGET_LOG")
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(directive));
+    let writer = (|| ProgressAwareWriter(std::io::stderr()))
+        .with_filter(|meta: &tracing::Metadata<'_>| {
+            *meta.level() <= tracing::Level::WARN || meta.target() == "cmd::stderr"
+        })
+        .or_else(|| ProgressAwareWriter(std::io::stdout()));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .event_format(LineFormat(style))
+        .init();
+}
@@ -0,0 +1,1023 @@
+// partitions.rs - Flash partitions, and the data and filesystem images written into them
+
+use crate::error::ToolError;
+use crate::journal::{create_dirs, remove_file, write_file};
+use crate::logging::run_logged;
+use crate::{
+    after_inner_attributes, elf_load_segments, find_device, is_esp_platform,
+    partition_write_command, DataCommands, DataFormat, FsCommands, FsImageOptions, GlueConfig,
+    MultiTargetTool, PartitionCommands, Platform, FLASH_ORIGIN,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum PartitionKind {
+    Bootloader,
+    App,
+    Nvs,
+    Data,
+}
+
+// A named flash partition; origin is an address on memory-mapped parts and an offset on ESP32
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Partition {
+    pub(crate) name: String,
+    pub(crate) kind: PartitionKind,
+    pub(crate) origin: u32,
+    pub(crate) length: u32,
+}
+
+// Device settings (serials, calibration, feature switches) for `data build`, from TOML or JSON
+#[derive(Debug, Deserialize)]
+struct DataDescription {
+    // Defaults to the platform's first nvs partition
+    partition: Option<String>,
+    format: Option<DataFormat>,
+    // Flash erase and write granularity, needed for sequential-storage
+    page_size: Option<u32>,
+    write_size: Option<u32>,
+    entries: Vec<DataEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataEntry {
+    key: String,
+    // TLV tag or sequential-storage key; defaults to the entry's position, starting at 1
+    id: Option<u16>,
+    #[serde(rename = "type")]
+    kind: DataType,
+    value: serde_json::Value,
+    // Fixed size of a str or bytes field in the raw format
+    len: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DataType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    Bool,
+    Str,
+    // Hex string, e.g. "deadbeef"
+    Bytes,
+}
+
+impl DataFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DataFormat::Raw => "raw",
+            DataFormat::Tlv => "tlv",
+            DataFormat::SequentialStorage => "sequential-storage",
+        }
+    }
+}
+
+impl DataType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DataType::U8 => "u8",
+            DataType::U16 => "u16",
+            DataType::U32 => "u32",
+            DataType::U64 => "u64",
+            DataType::I8 => "i8",
+            DataType::I16 => "i16",
+            DataType::I32 => "i32",
+            DataType::I64 => "i64",
+            DataType::F32 => "f32",
+            DataType::F64 => "f64",
+            DataType::Bool => "bool",
+            DataType::Str => "str",
+            DataType::Bytes => "bytes",
+        }
+    }
+}
+
+// Partition layout of a platform; bootloader platforms use their flash map
+fn platform_partitions(platform: &Platform) -> Vec<Partition> {
+    match &platform.bootloader {
+        Some(bootloader) => bootloader
+            .flash_map
+            .iter()
+            .map(|region| Partition {
+                name: region.name.clone(),
+                kind: match region.name.as_str() {
+                    "BOOTLOADER" => PartitionKind::Bootloader,
+                    "ACTIVE" | "DFU" | "SLOT0" | "SLOT1" => PartitionKind::App,
+                    _ => PartitionKind::Data,
+                },
+                origin: region.origin,
+                length: region.length,
+            })
+            .collect(),
+        None => platform.partitions.clone(),
+    }
+}
+
+// Starting layout: app, NVS and user data on STM32-style parts; ESP-IDF offsets on ESP32
+fn default_partitions(platform: &Platform) -> Vec<Partition> {
+    let partition = |name: &str, kind, origin, length| Partition {
+        name: name.to_string(),
+        kind,
+        origin,
+        length,
+    };
+    if is_esp_platform(platform) {
+        vec![
+            partition("NVS", PartitionKind::Nvs, 0x9000, 0x6000),
+            partition("FACTORY", PartitionKind::App, 0x10000, 0x10_0000),
+            partition("STORAGE", PartitionKind::Data, 0x11_0000, 0x4_0000),
+        ]
+    } else {
+        vec![
+            partition("APP", PartitionKind::App, FLASH_ORIGIN, 224 * 1024),
+            partition(
+                "NVS",
+                PartitionKind::Nvs,
+                FLASH_ORIGIN + 224 * 1024,
+                16 * 1024,
+            ),
+            partition(
+                "USER_DATA",
+                PartitionKind::Data,
+                FLASH_ORIGIN + 240 * 1024,
+                16 * 1024,
+            ),
+        ]
+    }
+}
+
+fn check_partitions(partitions: &[Partition]) -> Result<(), ToolError> {
+    if !partitions.iter().any(|p| p.kind == PartitionKind::App) {
+        return Err(ToolError::Config(
+            "Partition layout needs at least one app partition".to_string(),
+        ));
+    }
+    let mut ends = std::collections::HashMap::new();
+    for partition in partitions {
+        let end = partition
+            .origin
+            .checked_add(partition.length)
+            .ok_or_else(|| {
+                ToolError::Config(format!(
+                    "Partition {} ({:#x} + {:#x}) runs past the end of the 32-bit address space",
+                    partition.name, partition.origin, partition.length
+                ))
+            })?;
+        ends.insert(partition.name.as_str(), end);
+    }
+    let mut sorted: Vec<&Partition> = partitions.iter().collect();
+    sorted.sort_by_key(|p| p.origin);
+    for pair in sorted.windows(2) {
+        if ends[pair[0].name.as_str()] > pair[1].origin {
+            return Err(ToolError::Config(format!(
+                "Partitions {} and {} overlap",
+                pair[0].name, pair[1].name
+            )));
+        }
+    }
+    match partitions.iter().find(|p| p.length == 0) {
+        Some(p) => Err(ToolError::Config(format!("Partition {} is empty", p.name))),
+        None => Ok(()),
+    }
+}
+
+// ESP-IDF partition table CSV, as accepted by espflash --partition-table
+fn esp_partition_table(partitions: &[Partition]) -> String {
+    let mut csv = String::from(
+        "# Generated by multi-target-rs from glue.toml.\n\
+         # Name, Type, SubType, Offset, Size, Flags\n",
+    );
+    let mut apps = 0;
+    for partition in partitions {
+        let (kind, subtype) = match partition.kind {
+            // The second-stage bootloader lives outside the partition table
+            PartitionKind::Bootloader => continue,
+            PartitionKind::App => {
+                apps += 1;
+                if apps == 1 {
+                    ("app", "factory".to_string())
+                } else {
+                    ("app", format!("ota_{}", apps - 2))
+                }
+            }
+            PartitionKind::Nvs => ("data", "nvs".to_string()),
+            PartitionKind::Data => ("data", "undefined".to_string()),
+        };
+        csv.push_str(&format!(
+            "{}, {}, {}, {:#x}, {:#x},\n",
+            partition.name.to_lowercase(),
+            kind,
+            subtype,
+            partition.origin,
+            partition.length
+        ));
+    }
+    csv
+}
+
+// Little-endian bytes of one settings value, zero-padded to `len` when the raw format asks for it
+fn encode_data_entry(entry: &DataEntry, format: DataFormat) -> Result<Vec<u8>, String> {
+    let invalid = || format!("'{}' is not a valid {}", entry.key, entry.kind.as_str());
+    let int = || entry.value.as_i64().ok_or_else(invalid);
+    let uint = || entry.value.as_u64().ok_or_else(invalid);
+    let float = || entry.value.as_f64().ok_or_else(invalid);
+    let mut bytes = match entry.kind {
+        DataType::U8 => u8::try_from(uint()?)
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        DataType::U16 => u16::try_from(uint()?)
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        DataType::U32 => u32::try_from(uint()?)
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        DataType::U64 => uint()?.to_le_bytes().to_vec(),
+        DataType::I8 => i8::try_from(int()?)
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        DataType::I16 => i16::try_from(int()?)
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        DataType::I32 => i32::try_from(int()?)
+            .map_err(|_| invalid())?
+            .to_le_bytes()
+            .to_vec(),
+        DataType::I64 => int()?.to_le_bytes().to_vec(),
+        DataType::F32 => {
+            // `as` rounds to the nearest f32 and saturates to infinity; only accept values whose
+            // f32 reads back as the number written, so 0.1 passes but 1e39 or 16777217 don't
+            let value = float()?;
+            let narrowed = value as f32;
+            if narrowed.to_string().parse::<f64>() != Ok(value) {
+                return Err(format!(
+                    "'{}' is {}, which an f32 can't hold exactly (it would be {}); use f64",
+                    entry.key, value, narrowed
+                ));
+            }
+            narrowed.to_le_bytes().to_vec()
+        }
+        DataType::F64 => float()?.to_le_bytes().to_vec(),
+        DataType::Bool => vec![u8::from(entry.value.as_bool().ok_or_else(invalid)?)],
+        DataType::Str => entry
+            .value
+            .as_str()
+            .ok_or_else(invalid)?
+            .as_bytes()
+            .to_vec(),
+        DataType::Bytes => {
+            let hex = entry.value.as_str().ok_or_else(invalid)?;
+            // Checked before slicing two bytes at a time, which would split a multi-byte char
+            if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    if format == DataFormat::Raw && matches!(entry.kind, DataType::Str | DataType::Bytes) {
+        let len = entry.len.ok_or_else(|| {
+            format!(
+                "'{}' needs a fixed `len` in the raw format so the fields after it stay in place",
+                entry.key
+            )
+        })?;
+        if bytes.len() > len {
+            return Err(format!(
+                "'{}' is {} bytes, longer than its len of {}",
+                entry.key,
+                bytes.len(),
+                len
+            ));
+        }
+        bytes.resize(len, 0);
+    }
+    Ok(bytes)
+}
+
+// Settings serialized without padding: values back to back for raw, else records of
+// tag (u16 LE), length (u16 LE) and value, ended by the erased tag 0xffff
+fn encode_data_entries(
+    description: &DataDescription,
+    format: DataFormat,
+) -> Result<Vec<u8>, String> {
+    let mut image = Vec::new();
+    let mut tags = std::collections::HashSet::new();
+    for (index, entry) in description.entries.iter().enumerate() {
+        let value = encode_data_entry(entry, format)?;
+        if format == DataFormat::Raw {
+            image.extend(value);
+            continue;
+        }
+        let tag = entry.id.unwrap_or(index as u16 + 1);
+        if tag == 0xffff || !tags.insert(tag) {
+            return Err(format!(
+                "'{}' has a duplicate or reserved id {}",
+                entry.key, tag
+            ));
+        }
+        let len = u16::try_from(value.len())
+            .map_err(|_| format!("'{}' is longer than 64 KiB", entry.key))?;
+        image.extend(tag.to_le_bytes());
+        image.extend(len.to_le_bytes());
+        image.extend(value);
+    }
+    if format != DataFormat::Raw {
+        image.extend([0xff, 0xff]);
+    }
+    Ok(image)
+}
+
+// Host program that replays TLV records into a sequential-storage map on a RAM flash and dumps it
+fn sequential_storage_image_main(page_size: u32, write_size: u32) -> String {
+    format!(
+        r#"//! Generated by multi-target-rs: writes settings into a sequential-storage map image.
+//! Keys are u16 and values raw little-endian bytes: read them with
+//! `map::fetch_item::<u16, &[u8], _>` on the device.
+use embedded_storage_async::nor_flash::{{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+}};
+use sequential_storage::{{cache::NoCache, map}};
+
+#[derive(Debug)]
+struct OutOfBounds;
+
+impl NorFlashError for OutOfBounds {{
+    fn kind(&self) -> NorFlashErrorKind {{
+        NorFlashErrorKind::OutOfBounds
+    }}
+}}
+
+struct RamFlash(Vec<u8>);
+
+impl ErrorType for RamFlash {{
+    type Error = OutOfBounds;
+}}
+
+impl ReadNorFlash for RamFlash {{
+    const READ_SIZE: usize = 1;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {{
+        let start = offset as usize;
+        let source = self.0.get(start..start + bytes.len()).ok_or(OutOfBounds)?;
+        bytes.copy_from_slice(source);
+        Ok(())
+    }}
+
+    fn capacity(&self) -> usize {{
+        self.0.len()
+    }}
+}}
+
+impl NorFlash for RamFlash {{
+    const WRITE_SIZE: usize = {write_size};
+    const ERASE_SIZE: usize = {page_size};
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {{
+        self.0
+            .get_mut(from as usize..to as usize)
+            .ok_or(OutOfBounds)?
+            .fill(0xff);
+        Ok(())
+    }}
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {{
+        let start = offset as usize;
+        let target = self.0.get_mut(start..start + bytes.len()).ok_or(OutOfBounds)?;
+        for (cell, byte) in target.iter_mut().zip(bytes) {{
+            *cell &= byte;
+        }}
+        Ok(())
+    }}
+}}
+
+fn main() {{
+    let args: Vec<String> = std::env::args().collect();
+    let records = std::fs::read(&args[1]).expect("read settings records");
+    let size: u32 = args[3].parse().expect("partition size");
+    let mut flash = RamFlash(vec![0xff; size as usize]);
+    let mut buffer = vec![0u8; size as usize];
+    embassy_futures::block_on(async {{
+        let mut at = 0;
+        while at + 4 <= records.len() {{
+            let key = u16::from_le_bytes([records[at], records[at + 1]]);
+            if key == 0xffff {{
+                break;
+            }}
+            let len = u16::from_le_bytes([records[at + 2], records[at + 3]]) as usize;
+            let value: &[u8] = &records[at + 4..at + 4 + len];
+            map::store_item(&mut flash, 0..size, &mut NoCache::new(), &mut buffer, &key, &value)
+                .await
+                .expect("store settings item");
+            at += 4 + len;
+        }}
+    }});
+    std::fs::write(&args[2], &flash.0).expect("write image");
+}}
+"#,
+        page_size = page_size,
+        write_size = write_size
+    )
+}
+
+// Flat binary of an ELF image's loadable segments starting at the lowest address, with `data`
+// placed at `data_origin` and erased (0xff) flash in the gaps
+fn merge_flash_image(elf: &[u8], data_origin: u64, data: &[u8]) -> Result<(u64, Vec<u8>), String> {
+    let segments = elf_load_segments(elf)?;
+    let start = segments
+        .iter()
+        .map(|(address, _)| *address)
+        .chain([data_origin])
+        .min()
+        .unwrap_or(data_origin);
+    let end = segments
+        .iter()
+        .map(|(address, bytes)| address + bytes.len() as u64)
+        .chain([data_origin + data.len() as u64])
+        .max()
+        .unwrap_or(data_origin);
+    if end - start > 64 * 1024 * 1024 {
+        return Err(format!(
+            "the image spans {:#x}..{:#x}, too far apart for one flat binary",
+            start, end
+        ));
+    }
+    let mut image = vec![0xff; (end - start) as usize];
+    for (address, bytes) in segments.into_iter().chain([(data_origin, data)]) {
+        let at = (address - start) as usize;
+        image[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+    Ok((start, image))
+}
+
+impl MultiTargetTool {
+    pub(crate) fn handle_partition_command(&self, cmd: PartitionCommands) -> Result<(), ToolError> {
+        match cmd {
+            PartitionCommands::Generate { platform } => self.generate_partitions(&platform),
+        }
+    }
+
+    fn generate_partitions(&self, name: &str) -> Result<(), ToolError> {
+        info!("🗂️  Generating partition layout for '{}'", name);
+        let mut config = self.load_glue_config()?;
+        let platform = config
+            .platforms
+            .iter_mut()
+            .find(|p| p.name == name)
+            .ok_or_else(|| {
+                ToolError::Config(format!("Platform '{}' not found in glue.toml", name))
+            })?;
+
+        if platform.bootloader.is_some() {
+            info!("  ℹ️  Using the bootloader flash map from glue.toml");
+        } else if platform.partitions.is_empty() {
+            platform.partitions = default_partitions(platform);
+            self.save_glue_config(&config)?;
+            info!("  ✓ Added default partitions to glue.toml");
+        }
+
+        let platform = config
+            .platforms
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or_else(|| {
+                ToolError::Config(format!("Platform '{}' not found in glue.toml", name))
+            })?;
+        let partitions = platform_partitions(platform);
+        check_partitions(&partitions)?;
+
+        let app_path = self.project_root.join(format!("app-{}", name));
+        if is_esp_platform(platform) {
+            write_file(
+                app_path.join("partitions.csv"),
+                esp_partition_table(&partitions),
+            )?;
+            info!("  ✓ Wrote app-{}/partitions.csv", name);
+        } else if platform.bootloader.is_none() {
+            write_file(app_path.join("memory.x"), self.app_memory_x(platform)?)?;
+            info!("  ✓ Wrote app-{}/memory.x", name);
+        }
+
+        self.write_partition_constants(&config)?;
+
+        for partition in &partitions {
+            info!(
+                "    {:<16} {:#010x} ({} KiB)",
+                partition.name,
+                partition.origin,
+                partition.length / 1024
+            );
+        }
+        info!("✅ Partition layout generated");
+        if is_esp_platform(platform) {
+            info!("\nNext steps:");
+            info!(
+                "  1. Flash with: espflash flash --partition-table app-{}/partitions.csv",
+                name
+            );
+        }
+        Ok(())
+    }
+
+    // core-lib/src/partitions.rs: one module of constants per platform with a layout
+    fn write_partition_constants(&self, config: &GlueConfig) -> Result<(), ToolError> {
+        if let Some(core) = &config.core {
+            warn!(
+                "  ⚠ core-lib is the existing crate {}; not writing partition constants into it",
+                core.package
+            );
+            return Ok(());
+        }
+        let mut content = String::from(
+            "// Generated by multi-target-rs from glue.toml.\n\
+             // Run `multi-target-rs partitions generate <platform>` after changing partitions instead of editing this file.\n",
+        );
+        for platform in &config.platforms {
+            let partitions = platform_partitions(platform);
+            if partitions.is_empty() {
+                continue;
+            }
+            content.push_str(&format!(
+                "\n/// Flash partitions of the `{}` platform\npub mod {} {{\n",
+                platform.name,
+                platform.name.replace('-', "_")
+            ));
+            for partition in &partitions {
+                let constant = partition.name.to_uppercase().replace('-', "_");
+                content.push_str(&format!(
+                    "    pub const {0}_START: u32 = {1:#010x};\n    pub const {0}_SIZE: u32 = {2:#x};\n",
+                    constant, partition.origin, partition.length
+                ));
+            }
+            content.push_str("}\n");
+        }
+        write_file(
+            self.project_root.join("core-lib/src/partitions.rs"),
+            content,
+        )?;
+
+        let lib_path = self.project_root.join("core-lib/src/lib.rs");
+        let lib = fs::read_to_string(&lib_path)?;
+        if !lib.contains("pub mod partitions;") {
+            let at = after_inner_attributes(&lib);
+            write_file(
+                &lib_path,
+                format!("{}\npub mod partitions;\n{}", &lib[..at], &lib[at..]),
+            )?;
+        }
+        info!("  ✓ Wrote core-lib/src/partitions.rs");
+        Ok(())
+    }
+
+    pub(crate) fn handle_data_command(&self, cmd: DataCommands) -> Result<(), ToolError> {
+        match cmd {
+            DataCommands::Build {
+                platform,
+                file,
+                format,
+                output,
+            } => self
+                .build_data_image(&platform, &file, format, output)
+                .map(|_| ()),
+            DataCommands::Flash {
+                platform,
+                file,
+                device,
+            } => self.flash_data_image(&platform, &file, device.as_deref()),
+            DataCommands::Merge {
+                platform,
+                file,
+                release,
+            } => self.merge_data_image(&platform, &file, release),
+        }
+    }
+
+    // Serialize a settings description for its partition, padded with erased flash to the
+    // partition size; returns the partition and the image path
+    fn build_data_image(
+        &self,
+        platform: &str,
+        file: &Path,
+        format: Option<DataFormat>,
+        output: Option<PathBuf>,
+    ) -> Result<(Partition, PathBuf), ToolError> {
+        let config = self.load_glue_config()?;
+        let platform_config = config
+            .platforms
+            .iter()
+            .find(|p| p.name == platform)
+            .ok_or_else(|| ToolError::Config(format!("Platform '{}' not found", platform)))?;
+        let content = fs::read_to_string(file)
+            .map_err(|e| ToolError::Config(format!("Cannot read {}: {}", file.display(), e)))?;
+        let description: DataDescription =
+            if file.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                serde_json::from_str(&content)?
+            } else {
+                toml::from_str(&content)?
+            };
+        let format = format.or(description.format).unwrap_or(DataFormat::Tlv);
+
+        let partitions = platform_partitions(platform_config);
+        let partition = match &description.partition {
+            Some(name) => partitions
+                .iter()
+                .find(|p| p.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    ToolError::Config(format!(
+                        "Platform '{}' has no partition '{}'",
+                        platform, name
+                    ))
+                })?,
+            None => partitions
+                .iter()
+                .find(|p| p.kind == PartitionKind::Nvs)
+                .ok_or_else(|| {
+                    ToolError::Config(format!(
+                "Platform '{}' has no nvs partition. Run: multi-target-rs partitions generate {}",
+                platform, platform
+            ))
+                })?,
+        }
+        .clone();
+
+        info!(
+            "💾 Building {} settings image for {} ({} entries)",
+            format.as_str(),
+            partition.name,
+            description.entries.len()
+        );
+        let records = encode_data_entries(&description, format).map_err(ToolError::Config)?;
+        let path = output.unwrap_or_else(|| {
+            self.project_root
+                .join("artifacts")
+                .join(platform)
+                .join("data")
+                .join(format!("{}.bin", partition.name.to_lowercase()))
+        });
+        if let Some(parent) = path.parent() {
+            create_dirs(parent)?;
+        }
+
+        let image = if format == DataFormat::SequentialStorage {
+            self.sequential_storage_image(&description, &partition, &records, &path)?
+        } else {
+            records
+        };
+        if image.len() > partition.length as usize {
+            return Err(ToolError::Config(format!(
+                "The settings need {} bytes but {} holds {}",
+                image.len(),
+                partition.name,
+                partition.length
+            )));
+        }
+        let mut padded = image;
+        let used = padded.len();
+        padded.resize(partition.length as usize, 0xff);
+        write_file(&path, padded)?;
+        for (index, entry) in description.entries.iter().enumerate() {
+            match format {
+                DataFormat::Raw => info!("    {:<20} {}", entry.key, entry.kind.as_str()),
+                _ => info!(
+                    "    {:<20} {:<5} (id {})",
+                    entry.key,
+                    entry.kind.as_str(),
+                    entry.id.unwrap_or(index as u16 + 1)
+                ),
+            }
+        }
+        info!(
+            "  ✓ Wrote {} ({} of {} bytes used, for {:#010x})",
+            path.strip_prefix(&self.project_root)
+                .unwrap_or(&path)
+                .display(),
+            used,
+            partition.length,
+            partition.origin
+        );
+        Ok((partition, path))
+    }
+
+    // sequential-storage's on-flash map is produced by the crate itself, through a small host
+    // program under tools/data-image/ that replays the TLV records
+    fn sequential_storage_image(
+        &self,
+        description: &DataDescription,
+        partition: &Partition,
+        records: &[u8],
+        output: &Path,
+    ) -> Result<Vec<u8>, ToolError> {
+        let page_size = description.page_size.ok_or_else(|| {
+            ToolError::Config(
+                "The sequential-storage format needs the flash's `page_size` (erase size)"
+                    .to_string(),
+            )
+        })?;
+        if page_size == 0
+            || !partition.length.is_multiple_of(page_size)
+            || partition.length / page_size < 2
+        {
+            return Err(ToolError::Config(format!(
+                "{} ({} bytes) must hold at least two whole {}-byte pages for sequential-storage",
+                partition.name, partition.length, page_size
+            )));
+        }
+        let tool_dir = self.project_root.join("tools").join("data-image");
+        create_dirs(tool_dir.join("src"))?;
+        write_file(
+            tool_dir.join("Cargo.toml"),
+            r#"[package]
+name = "data-image"
+version = "0.1.0"
+edition = "2021"
+publish = false
+
+# Host-only helper, kept out of the firmware workspace
+[workspace]
+
+[dependencies]
+sequential-storage = "3"
+embedded-storage-async = "0.4"
+embassy-futures = "0.1"
+"#,
+        )?;
+        write_file(
+            tool_dir.join("src/main.rs"),
+            sequential_storage_image_main(page_size, description.write_size.unwrap_or(4)),
+        )?;
+        let records_path = output.with_extension("records");
+        write_file(&records_path, records)?;
+
+        info!("  🔧 Running tools/data-image with sequential-storage");
+        let status = run_logged(
+            Command::new("cargo")
+                .args(["run", "--quiet", "--release", "--manifest-path"])
+                .arg(tool_dir.join("Cargo.toml"))
+                .arg("--")
+                .arg(&records_path)
+                .arg(output)
+                .arg(partition.length.to_string())
+                .current_dir(&self.project_root),
+            true,
+        )?
+        .status;
+        remove_file(&records_path)?;
+        if !status.success() {
+            return Err(ToolError::Build(
+                "tools/data-image failed to write the sequential-storage map".into(),
+            ));
+        }
+        let image = fs::read(output)?;
+        // Everything after the last programmed byte is erased flash; keep the used length honest
+        let used = image
+            .iter()
+            .rposition(|&byte| byte != 0xff)
+            .map_or(0, |at| at + 1);
+        Ok(image[..used].to_vec())
+    }
+
+    fn flash_data_image(
+        &self,
+        platform: &str,
+        file: &Path,
+        device: Option<&str>,
+    ) -> Result<(), ToolError> {
+        let (partition, image) = self.build_data_image(platform, file, None, None)?;
+        self.write_partition(platform, device, &partition, &image)?;
+        info!("✅ Settings written to {}", partition.name);
+        Ok(())
+    }
+
+    fn write_partition(
+        &self,
+        platform: &str,
+        device: Option<&str>,
+        partition: &Partition,
+        image: &Path,
+    ) -> Result<(), ToolError> {
+        let config = self.load_glue_config()?;
+        let platform_config = config
+            .platforms
+            .iter()
+            .find(|p| p.name == platform)
+            .ok_or_else(|| ToolError::Config(format!("Platform '{}' not found", platform)))?;
+        let device = device.map(|name| find_device(&config, name)).transpose()?;
+        let mut cmd = partition_write_command(platform_config, device, partition, image)
+            .map_err(ToolError::Config)?;
+        info!(
+            "⚡ Writing {} to {:#010x} with {}",
+            partition.name,
+            partition.origin,
+            cmd.get_program().to_string_lossy()
+        );
+        if !run_logged(&mut cmd, true)?.status.success() {
+            return Err(ToolError::Hardware(format!(
+                "Flashing the {} partition failed",
+                partition.name
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn handle_fs_command(&self, cmd: FsCommands) -> Result<(), ToolError> {
+        match cmd {
+            FsCommands::Build { image, output } => self.build_fs_image(&image, output).map(|_| ()),
+            FsCommands::Flash { image, device } => {
+                let (partition, path) = self.build_fs_image(&image, None)?;
+                self.write_partition(&image.target, device.as_deref(), &partition, &path)?;
+                info!("✅ {} written to {}", image.dir.display(), partition.name);
+                Ok(())
+            }
+        }
+    }
+
+    // littlefs image of a directory, sized to the whole partition, built with mklittlefs
+    fn build_fs_image(
+        &self,
+        options: &FsImageOptions,
+        output: Option<PathBuf>,
+    ) -> Result<(Partition, PathBuf), ToolError> {
+        let config = self.load_glue_config()?;
+        let platform = &options.target;
+        let platform_config = config
+            .platforms
+            .iter()
+            .find(|p| &p.name == platform)
+            .ok_or_else(|| ToolError::Config(format!("Platform '{}' not found", platform)))?;
+        let partitions = platform_partitions(platform_config);
+        let partition = match &options.partition {
+            Some(name) => partitions
+                .iter()
+                .find(|p| p.name.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    ToolError::Config(format!(
+                        "Platform '{}' has no partition '{}'",
+                        platform, name
+                    ))
+                })?,
+            None => partitions
+                .iter()
+                .find(|p| p.kind == PartitionKind::Data)
+                .ok_or_else(|| {
+                    ToolError::Config(format!(
+                "Platform '{}' has no data partition. Run: multi-target-rs partitions generate {}",
+                platform, platform
+            ))
+                })?,
+        }
+        .clone();
+        if options.block_size == 0 || !partition.length.is_multiple_of(options.block_size) {
+            return Err(ToolError::Config(format!(
+                "{} ({} bytes) is not a whole number of {}-byte blocks",
+                partition.name, partition.length, options.block_size
+            )));
+        }
+
+        let dir = self.project_root.join(&options.dir);
+        if !dir.is_dir() {
+            return Err(ToolError::Config(format!(
+                "{} is not a directory",
+                options.dir.display()
+            )));
+        }
+        let mut pending = vec![dir.clone()];
+        let (mut files, mut bytes) = (0, 0);
+        while let Some(path) = pending.pop() {
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    pending.push(entry.path());
+                } else {
+                    files += 1;
+                    bytes += entry.metadata()?.len();
+                }
+            }
+        }
+        if bytes > u64::from(partition.length) {
+            return Err(ToolError::Config(format!(
+                "{} holds {} bytes of files, more than {} ({} bytes)",
+                options.dir.display(),
+                bytes,
+                partition.name,
+                partition.length
+            )));
+        }
+
+        let path = output.unwrap_or_else(|| {
+            self.project_root
+                .join("artifacts")
+                .join(platform)
+                .join("fs")
+                .join(format!("{}.bin", partition.name.to_lowercase()))
+        });
+        if let Some(parent) = path.parent() {
+            create_dirs(parent)?;
+        }
+        info!(
+            "📁 Packing {} ({} files, {} bytes) into a littlefs image for {}",
+            options.dir.display(),
+            files,
+            bytes,
+            partition.name
+        );
+        // mklittlefs lists every file it packs on stdout; only its errors are logged
+        let output = run_logged(
+            Command::new("mklittlefs")
+                .arg("-c")
+                .arg(&dir)
+                .args(["-b", &options.block_size.to_string()])
+                .args(["-p", "256"])
+                .args(["-s", &partition.length.to_string()])
+                .arg(&path)
+                .current_dir(&self.project_root),
+            false,
+        )
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => ToolError::Build(
+                "mklittlefs is not installed. Download it from \
+                 https://github.com/earlephilhower/mklittlefs/releases and put it on PATH"
+                    .to_string(),
+            ),
+            _ => ToolError::Build(format!("Could not run mklittlefs: {}", e)),
+        })?;
+        if !output.status.success() {
+            return Err(ToolError::Build(format!(
+                "mklittlefs could not pack {}",
+                options.dir.display()
+            )));
+        }
+        info!(
+            "  ✓ Wrote {} ({} blocks of {} bytes, for {:#010x})",
+            path.strip_prefix(&self.project_root)
+                .unwrap_or(&path)
+                .display(),
+            partition.length / options.block_size,
+            options.block_size,
+            partition.origin
+        );
+        Ok((partition, path))
+    }
+
+    fn merge_data_image(
+        &self,
+        platform: &str,
+        file: &Path,
+        release: bool,
+    ) -> Result<(), ToolError> {
+        let config = self.load_glue_config()?;
+        let platform_config = config
+            .platforms
+            .iter()
+            .find(|p| p.name == platform)
+            .ok_or_else(|| ToolError::Config(format!("Platform '{}' not found", platform)))?;
+        if is_esp_platform(platform_config) {
+            return Err(ToolError::Config("ESP images aren't flat binaries; flash the settings with `data flash` or merge with `espflash save-image --merge`".into()));
+        }
+        let profile = if release { "release" } else { "debug" };
+        let manifest = self.load_artifact_manifest(platform, profile)?;
+        let artifact = manifest.app_image()?;
+        let (partition, image) = self.build_data_image(platform, file, None, None)?;
+
+        let elf = fs::read(self.project_root.join(&artifact.path))?;
+        let data = fs::read(&image)?;
+        let (start, merged) = merge_flash_image(&elf, u64::from(partition.origin), &data)
+            .map_err(|e| ToolError::Build(format!("Cannot merge {}: {}", artifact.path, e)))?;
+        let relative = Path::new("artifacts")
+            .join(platform)
+            .join(profile)
+            .join(format!(
+                "{}-{}.bin",
+                artifact.name.trim_end_matches(".exe"),
+                partition.name.to_lowercase()
+            ));
+        write_file(self.project_root.join(&relative), &merged)?;
+        info!(
+            "✅ Wrote {} ({} bytes from {:#010x}); flash it with: probe-rs download --binary-format bin --base-address {:#x} {}",
+            relative.display(),
+            merged.len(),
+            start,
+            start,
+            relative.display()
+        );
+        Ok(())
+    }
+}
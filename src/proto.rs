@@ -0,0 +1,575 @@
+// proto.rs - `proto generate`: message crates shared by firmware and host tools
+
+use crate::error::ToolError;
+use crate::journal::{create_dirs, write_file};
+use crate::{MultiTargetTool, ProtoCommands};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use tracing::info;
+
+// Messages exchanged between firmware and host tools, from proto/messages.toml
+#[derive(Debug, Deserialize)]
+struct ProtoSchema {
+    #[serde(default)]
+    format: ProtoFormat,
+    #[serde(default)]
+    enums: Vec<ProtoEnum>,
+    messages: Vec<ProtoMessage>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ProtoFormat {
+    // serde + postcard with COBS framing; heapless strings keep the firmware allocation-free
+    #[default]
+    Postcard,
+    // prost messages with length-delimited framing; the firmware needs an allocator
+    Protobuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProtoEnum {
+    name: String,
+    variants: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProtoMessage {
+    name: String,
+    fields: Vec<ProtoField>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProtoField {
+    name: String,
+    // bool, u8..u64, i8..i64, f32, f64, string, bytes, or an enum or message name
+    #[serde(rename = "type")]
+    kind: String,
+    // Capacity of a string or bytes field (required for postcard)
+    max_len: Option<usize>,
+    #[serde(default)]
+    optional: bool,
+}
+
+const STARTER_PROTO_SCHEMA: &str = r#"# Messages shared by the firmware (protocol crate) and host tools (protocol-host crate).
+# Run `multi-target-rs proto generate` after editing.
+format = "postcard"
+
+[[enums]]
+name = "Command"
+variants = ["Start", "Stop", "Reset"]
+
+[[messages]]
+name = "Request"
+fields = [
+    { name = "id", type = "u16" },
+    { name = "command", type = "Command" },
+]
+
+[[messages]]
+name = "Reading"
+fields = [
+    { name = "sensor", type = "u8" },
+    { name = "value", type = "f32" },
+    { name = "label", type = "string", max_len = 16, optional = true },
+]
+"#;
+
+const PROTO_SCALARS: &[&str] = &[
+    "bool", "u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64", "f32", "f64",
+];
+
+fn check_proto_schema(schema: &ProtoSchema) -> Result<(), String> {
+    let is_ident = |name: &str| {
+        name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    let mut types = std::collections::HashSet::new();
+    let names = schema
+        .enums
+        .iter()
+        .map(|e| &e.name)
+        .chain(schema.messages.iter().map(|m| &m.name));
+    for name in names {
+        if !is_ident(name) || name == "Message" || !types.insert(name.as_str()) {
+            return Err(format!("'{}' is not a valid, unique type name", name));
+        }
+    }
+    for proto_enum in &schema.enums {
+        if proto_enum.variants.is_empty() || !proto_enum.variants.iter().all(|v| is_ident(v)) {
+            return Err(format!(
+                "Enum {} needs identifier variants",
+                proto_enum.name
+            ));
+        }
+    }
+    for message in &schema.messages {
+        for field in &message.fields {
+            let known = PROTO_SCALARS.contains(&field.kind.as_str())
+                || matches!(field.kind.as_str(), "string" | "bytes")
+                || types.contains(field.kind.as_str());
+            if !is_ident(&field.name) || !known || field.kind == message.name {
+                return Err(format!(
+                    "{}.{}: unknown or self-referencing type '{}'",
+                    message.name, field.name, field.kind
+                ));
+            }
+            if schema.format == ProtoFormat::Postcard
+                && matches!(field.kind.as_str(), "string" | "bytes")
+                && field.max_len.is_none()
+            {
+                return Err(format!(
+                    "{}.{}: postcard strings and bytes need a max_len",
+                    message.name, field.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Rust type and, for protobuf, the prost attribute of one field
+fn proto_field_type(schema: &ProtoSchema, field: &ProtoField) -> (String, Option<String>) {
+    let is_enum = schema.enums.iter().any(|e| e.name == field.kind);
+    if schema.format == ProtoFormat::Postcard {
+        let rust = match field.kind.as_str() {
+            "string" => format!("heapless::String<{}>", field.max_len.unwrap_or_default()),
+            "bytes" => format!("heapless::Vec<u8, {}>", field.max_len.unwrap_or_default()),
+            other => other.to_string(),
+        };
+        let rust = if field.optional {
+            format!("Option<{}>", rust)
+        } else {
+            rust
+        };
+        return (rust, None);
+    }
+    let (rust, prost) = match field.kind.as_str() {
+        "bool" => ("bool".to_string(), "bool".to_string()),
+        "u8" | "u16" | "u32" => ("u32".to_string(), "uint32".to_string()),
+        "i8" | "i16" | "i32" => ("i32".to_string(), "int32".to_string()),
+        "u64" => ("u64".to_string(), "uint64".to_string()),
+        "i64" => ("i64".to_string(), "int64".to_string()),
+        "f32" => ("f32".to_string(), "float".to_string()),
+        "f64" => ("f64".to_string(), "double".to_string()),
+        "string" => ("alloc::string::String".to_string(), "string".to_string()),
+        "bytes" => (
+            "alloc::vec::Vec<u8>".to_string(),
+            "bytes = \"vec\"".to_string(),
+        ),
+        name if is_enum => ("i32".to_string(), format!("enumeration = \"{}\"", name)),
+        // Message fields are always optional in protobuf
+        name => {
+            return (
+                format!("Option<{}>", name),
+                Some("message, optional".to_string()),
+            )
+        }
+    };
+    if field.optional {
+        (
+            format!("Option<{}>", rust),
+            Some(format!("{}, optional", prost)),
+        )
+    } else {
+        (rust, Some(prost))
+    }
+}
+
+// protocol/src/lib.rs: the message types plus frame encode/decode for the firmware
+fn proto_device_lib(schema: &ProtoSchema) -> String {
+    let postcard = schema.format == ProtoFormat::Postcard;
+    let mut lib = String::from(
+        "//! Generated by multi-target-rs from proto/messages.toml.\n\
+         //! Run `multi-target-rs proto generate` after editing the schema instead of this file.\n",
+    );
+    if postcard {
+        lib.push_str(
+            "//! Messages are postcard-encoded and COBS-framed, so every frame ends in a 0 byte.\n\
+             #![no_std]\n\n\
+             pub use heapless;\n\
+             pub use postcard::Error;\n\
+             use serde::{Deserialize, Serialize};\n",
+        );
+    } else {
+        lib.push_str(
+            "//! Messages are protobuf-encoded with a varint length prefix; the firmware needs a\n\
+             //! global allocator for strings and bytes.\n\
+             #![no_std]\n\n\
+             extern crate alloc;\n\n\
+             pub use prost;\n\
+             pub use prost::{DecodeError, EncodeError};\n",
+        );
+    }
+
+    for proto_enum in &schema.enums {
+        if postcard {
+            lib.push_str(
+                "\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]\n",
+            );
+            lib.push_str(&format!("pub enum {} {{\n", proto_enum.name));
+            for variant in &proto_enum.variants {
+                lib.push_str(&format!("    {},\n", variant));
+            }
+        } else {
+            lib.push_str("\n#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, prost::Enumeration)]\n#[repr(i32)]\n");
+            lib.push_str(&format!("pub enum {} {{\n", proto_enum.name));
+            for (index, variant) in proto_enum.variants.iter().enumerate() {
+                lib.push_str(&format!("    {} = {},\n", variant, index));
+            }
+        }
+        lib.push_str("}\n");
+    }
+
+    for message in &schema.messages {
+        if postcard {
+            lib.push_str("\n#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+        } else {
+            lib.push_str("\n#[derive(Clone, PartialEq, prost::Message)]\n");
+        }
+        lib.push_str(&format!("pub struct {} {{\n", message.name));
+        for (index, field) in message.fields.iter().enumerate() {
+            let (rust, prost) = proto_field_type(schema, field);
+            if let Some(prost) = prost {
+                lib.push_str(&format!(
+                    "    #[prost({}, tag = \"{}\")]\n",
+                    prost,
+                    index + 1
+                ));
+            }
+            lib.push_str(&format!("    pub {}: {},\n", field.name, rust));
+        }
+        lib.push_str("}\n");
+    }
+
+    if postcard {
+        lib.push_str("\n/// Any message, so one frame type can carry all of them\n");
+        lib.push_str(
+            "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\npub enum Message {\n",
+        );
+        for message in &schema.messages {
+            lib.push_str(&format!("    {0}({0}),\n", message.name));
+        }
+        lib.push_str(
+            "}\n\n\
+             /// Encode `message` as one COBS frame into `buf`, returning the used part\n\
+             pub fn encode<'a, T: Serialize>(message: &T, buf: &'a mut [u8]) -> Result<&'a mut [u8], Error> {\n    \
+                 postcard::to_slice_cobs(message, buf)\n\
+             }\n\n\
+             /// Decode one COBS frame, including its 0 terminator; the frame is decoded in place\n\
+             pub fn decode<'a, T: Deserialize<'a>>(frame: &'a mut [u8]) -> Result<T, Error> {\n    \
+                 postcard::from_bytes_cobs(frame)\n\
+             }\n",
+        );
+    } else {
+        lib.push_str(
+            "\n/// Encode `message` with its length prefix into `buf`, returning the bytes written\n\
+             pub fn encode<T: prost::Message>(message: &T, mut buf: &mut [u8]) -> Result<usize, EncodeError> {\n    \
+                 let capacity = buf.len();\n    \
+                 message.encode_length_delimited(&mut buf)?;\n    \
+                 Ok(capacity - buf.len())\n\
+             }\n\n\
+             /// Decode one length-prefixed message\n\
+             pub fn decode<T: prost::Message + Default>(frame: &[u8]) -> Result<T, DecodeError> {\n    \
+                 T::decode_length_delimited(frame)\n\
+             }\n",
+        );
+    }
+    lib
+}
+
+// protocol-host/src/lib.rs: frames over any std byte stream, e.g. a serial port or TCP socket
+fn proto_host_lib(format: ProtoFormat) -> String {
+    let mut lib = String::from(
+        "//! Generated by multi-target-rs: the host side of the `protocol` crate.\n\
+         pub use protocol::*;\n\
+         use std::io::{self, Read, Write};\n\n",
+    );
+    if format == ProtoFormat::Postcard {
+        lib.push_str(
+            "/// Encode `message` as one COBS frame\n\
+             pub fn to_frame<T: serde::Serialize>(message: &T) -> Result<Vec<u8>, Error> {\n    \
+                 postcard::to_stdvec_cobs(message)\n\
+             }\n\n\
+             /// Write `message` to `writer` as one frame\n\
+             pub fn write_frame<W: Write, T: serde::Serialize>(writer: &mut W, message: &T) -> io::Result<()> {\n    \
+                 let frame = to_frame(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;\n    \
+                 writer.write_all(&frame)\n\
+             }\n\n\
+             /// Reads frames from a byte stream and decodes them\n\
+             pub struct FrameReader<R> {\n    \
+                 inner: R,\n    \
+                 frame: Vec<u8>,\n\
+             }\n\n\
+             impl<R: Read> FrameReader<R> {\n    \
+                 pub fn new(inner: R) -> Self {\n        \
+                     Self { inner, frame: Vec::new() }\n    \
+                 }\n\n    \
+                 /// The next message, or None once the stream ends\n    \
+                 pub fn read<T: serde::de::DeserializeOwned>(&mut self) -> io::Result<Option<T>> {\n        \
+                     self.frame.clear();\n        \
+                     let mut byte = [0u8; 1];\n        \
+                     loop {\n            \
+                         if self.inner.read(&mut byte)? == 0 {\n                \
+                             return Ok(None);\n            \
+                         }\n            \
+                         self.frame.push(byte[0]);\n            \
+                         if byte[0] == 0 {\n                \
+                             break;\n            \
+                         }\n        \
+                     }\n        \
+                     postcard::from_bytes_cobs(&mut self.frame)\n            \
+                         .map(Some)\n            \
+                         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))\n    \
+                 }\n\
+             }\n",
+        );
+    } else {
+        lib.push_str(
+            "/// Encode `message` with its length prefix\n\
+             pub fn to_frame<T: prost::Message>(message: &T) -> Vec<u8> {\n    \
+                 message.encode_length_delimited_to_vec()\n\
+             }\n\n\
+             /// Write `message` to `writer` as one frame\n\
+             pub fn write_frame<W: Write, T: prost::Message>(writer: &mut W, message: &T) -> io::Result<()> {\n    \
+                 writer.write_all(&to_frame(message))\n\
+             }\n\n\
+             /// Reads length-prefixed messages from a byte stream\n\
+             pub struct FrameReader<R> {\n    \
+                 inner: R,\n\
+             }\n\n\
+             impl<R: Read> FrameReader<R> {\n    \
+                 pub fn new(inner: R) -> Self {\n        \
+                     Self { inner }\n    \
+                 }\n\n    \
+                 /// The next message, or None once the stream ends\n    \
+                 pub fn read<T: prost::Message + Default>(&mut self) -> io::Result<Option<T>> {\n        \
+                     let mut len = 0usize;\n        \
+                     let mut byte = [0u8; 1];\n        \
+                     for shift in (0..64).step_by(7) {\n            \
+                         if self.inner.read(&mut byte)? == 0 {\n                \
+                             return Ok(None);\n            \
+                         }\n            \
+                         len |= usize::from(byte[0] & 0x7f) << shift;\n            \
+                         if byte[0] & 0x80 == 0 {\n                \
+                             break;\n            \
+                         }\n        \
+                     }\n        \
+                     let mut body = vec![0u8; len];\n        \
+                     self.inner.read_exact(&mut body)?;\n        \
+                     T::decode(body.as_slice())\n            \
+                         .map(Some)\n            \
+                         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))\n    \
+                 }\n\
+             }\n",
+        );
+    }
+    lib
+}
+
+// Value of one field for the generated round-trip tests
+fn proto_sample_value(schema: &ProtoSchema, field: &ProtoField) -> String {
+    let postcard = schema.format == ProtoFormat::Postcard;
+    let text = &"abc"[..field.max_len.unwrap_or(3).min(3)];
+    let value = match field.kind.as_str() {
+        "bool" => "true".to_string(),
+        "f32" | "f64" => "1.5".to_string(),
+        "string" if postcard => format!("heapless::String::try_from(\"{}\").unwrap()", text),
+        "string" => format!("\"{}\".to_string()", text),
+        "bytes" if postcard => format!(
+            "heapless::Vec::from_slice(&{:?}).unwrap()",
+            &[1u8, 2, 3][..text.len()]
+        ),
+        "bytes" => format!("vec!{:?}", &[1u8, 2, 3][..text.len()]),
+        kind if PROTO_SCALARS.contains(&kind) => "7".to_string(),
+        name => match schema.enums.iter().find(|e| e.name == name) {
+            Some(proto_enum) if postcard => format!("{}::{}", name, proto_enum.variants[0]),
+            Some(proto_enum) => format!("{}::{} as i32", name, proto_enum.variants[0]),
+            None if postcard => format!("sample_{}()", name.to_lowercase()),
+            // prost wraps message fields in Option
+            None => return format!("Some(sample_{}())", name.to_lowercase()),
+        },
+    };
+    if field.optional {
+        format!("Some({})", value)
+    } else {
+        value
+    }
+}
+
+// protocol-host/tests/round_trip.rs: every message goes firmware encode -> host decode and back
+fn proto_round_trip_tests(schema: &ProtoSchema) -> String {
+    let postcard = schema.format == ProtoFormat::Postcard;
+    // Room for the largest message: fixed fields plus every string and byte capacity
+    let buffer: usize = 256
+        + schema
+            .messages
+            .iter()
+            .flat_map(|m| &m.fields)
+            .filter_map(|f| f.max_len)
+            .sum::<usize>();
+    let mut tests = String::from(
+        "//! Generated by multi-target-rs: each message survives the firmware's encoding and the\n\
+         //! host's decoding, and the other way round.\n\
+         use protocol_host::*;\n",
+    );
+    for message in &schema.messages {
+        let lower = message.name.to_lowercase();
+        tests.push_str(&format!(
+            "\nfn sample_{}() -> {} {{\n    {} {{\n",
+            lower, message.name, message.name
+        ));
+        for field in &message.fields {
+            tests.push_str(&format!(
+                "        {}: {},\n",
+                field.name,
+                proto_sample_value(schema, field)
+            ));
+        }
+        tests.push_str("    }\n}\n");
+    }
+    for message in &schema.messages {
+        let lower = message.name.to_lowercase();
+        tests.push_str(&format!("\n#[test]\nfn {}_round_trips() {{\n", lower));
+        if postcard {
+            tests.push_str(&format!(
+                "    let message = Message::{}(sample_{}());\n    \
+                     let mut buf = [0u8; {}];\n    \
+                     let frame = protocol::encode(&message, &mut buf).unwrap().to_vec();\n    \
+                     let mut reader = FrameReader::new(frame.as_slice());\n    \
+                     assert_eq!(reader.read::<Message>().unwrap(), Some(message.clone()));\n\n    \
+                     let mut host_frame = to_frame(&message).unwrap();\n    \
+                     assert_eq!(protocol::decode::<Message>(&mut host_frame).unwrap(), message);\n",
+                message.name, lower, buffer
+            ));
+        } else {
+            tests.push_str(&format!(
+                "    let message = sample_{}();\n    \
+                     let mut buf = [0u8; {}];\n    \
+                     let len = protocol::encode(&message, &mut buf).unwrap();\n    \
+                     let mut reader = FrameReader::new(&buf[..len]);\n    \
+                     assert_eq!(reader.read::<{}>().unwrap(), Some(message.clone()));\n\n    \
+                     let host_frame = to_frame(&message);\n    \
+                     assert_eq!(protocol::decode::<{}>(&host_frame).unwrap(), message);\n",
+                lower, buffer, message.name, message.name
+            ));
+        }
+        tests.push_str("}\n");
+    }
+    tests
+}
+
+impl MultiTargetTool {
+    pub(crate) fn handle_proto_command(&self, cmd: ProtoCommands) -> Result<(), ToolError> {
+        match cmd {
+            ProtoCommands::Generate { schema } => self.proto_generate(&schema),
+        }
+    }
+
+    // Generate protocol/ (no_std, for the firmware) and protocol-host/ (std) from the schema,
+    // add both to the workspace and let every platform app depend on protocol
+    fn proto_generate(&self, schema_path: &Path) -> Result<(), ToolError> {
+        let path = self.project_root.join(schema_path);
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                create_dirs(parent)?;
+            }
+            write_file(&path, STARTER_PROTO_SCHEMA)?;
+            info!("  ✓ Wrote a starter schema to {}", schema_path.display());
+        }
+        let schema: ProtoSchema = toml::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| ToolError::Config(format!("{}: {}", schema_path.display(), e)))?;
+        check_proto_schema(&schema)
+            .map_err(|e| ToolError::Config(format!("{}: {}", schema_path.display(), e)))?;
+        let postcard = schema.format == ProtoFormat::Postcard;
+        info!(
+            "📨 Generating {} message code for {} messages",
+            if postcard { "postcard" } else { "protobuf" },
+            schema.messages.len()
+        );
+
+        let device_deps = if postcard {
+            "serde = { version = \"1.0\", default-features = false, features = [\"derive\"] }\n\
+             postcard = { version = \"1.0\", default-features = false }\n\
+             heapless = { version = \"0.8\", features = [\"serde\"] }\n"
+        } else {
+            "prost = { version = \"0.13\", default-features = false, features = [\"derive\"] }\n"
+        };
+        let host_deps = if postcard {
+            "serde = \"1.0\"\npostcard = { version = \"1.0\", features = [\"use-std\"] }\n"
+        } else {
+            "prost = \"0.13\"\n"
+        };
+        let mut new_members = Vec::new();
+        for (name, deps, lib) in [
+            (
+                "protocol",
+                device_deps.to_string(),
+                proto_device_lib(&schema),
+            ),
+            (
+                "protocol-host",
+                format!("protocol = {{ path = \"../protocol\" }}\n{}", host_deps),
+                proto_host_lib(schema.format),
+            ),
+        ] {
+            let crate_path = self.project_root.join(name);
+            if !crate_path.exists() {
+                new_members.push(name.to_string());
+            }
+            create_dirs(crate_path.join("src"))?;
+            write_file(
+                crate_path.join("Cargo.toml"),
+                format!(
+                    "# Generated by multi-target-rs proto generate\n\
+                     [package]\n\
+                     name = \"{}\"\n\
+                     version = \"0.1.0\"\n\
+                     edition.workspace = true\n\
+                     authors.workspace = true\n\
+                     license.workspace = true\n\n\
+                     [dependencies]\n{}",
+                    name, deps
+                ),
+            )?;
+            write_file(crate_path.join("src/lib.rs"), lib)?;
+            info!("  ✓ Wrote {}/src/lib.rs", name);
+        }
+        let tests_path = self.project_root.join("protocol-host/tests");
+        create_dirs(&tests_path)?;
+        write_file(
+            tests_path.join("round_trip.rs"),
+            proto_round_trip_tests(&schema),
+        )?;
+        info!("  ✓ Wrote protocol-host/tests/round_trip.rs");
+        if !new_members.is_empty() {
+            self.update_workspace_members(&new_members)?;
+        }
+
+        let config = self.load_glue_config()?;
+        for platform in &config.platforms {
+            let manifest_path = self
+                .project_root
+                .join(format!("app-{}", platform.name))
+                .join("Cargo.toml");
+            let Ok(manifest) = fs::read_to_string(&manifest_path) else {
+                continue;
+            };
+            if manifest.lines().any(|line| line.starts_with("protocol ")) {
+                continue;
+            }
+            let updated = manifest.replacen(
+                "[dependencies]\n",
+                "[dependencies]\nprotocol = { path = \"../protocol\" }\n",
+                1,
+            );
+            write_file(&manifest_path, updated)?;
+            info!("  ✓ app-{} depends on protocol", platform.name);
+        }
+
+        info!("✅ Message code generated");
+        info!("  multi-target-rs test    # Runs the round-trip tests in protocol-host");
+        Ok(())
+    }
+}
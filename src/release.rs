@@ -0,0 +1,897 @@
+// release.rs - `version`, `publish` and `release`
+
+use crate::error::ToolError;
+use crate::journal::{create_dirs, remove_tree, walk_paths, write_file, Journal};
+use crate::logging::{progress_bar, run_logged};
+use crate::{current_date, platform_crates, BuildTool, MultiTargetTool, Platform, VersionCommands};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tracing::{error, info, warn};
+
+// Replace the version in a manifest's [package] (or [workspace.package]) table; crates inheriting
+// it from the workspace, or without one, are returned unchanged
+fn set_package_version(manifest: &str, section: &str, version: &str) -> String {
+    let mut in_package = false;
+    let mut replaced = false;
+    let mut lines: Vec<String> = Vec::new();
+    for line in manifest.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_package = trimmed == section;
+        } else if in_package && !replaced && trimmed.starts_with("version =") {
+            lines.push(format!("version = \"{}\"", version));
+            replaced = true;
+            continue;
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+// Manifest fields crates.io needs (first) and ones it merely recommends (second) that a package
+// table lacks, looking through `field.workspace = true` to [workspace.package]
+fn missing_publish_metadata(
+    package: &toml::Table,
+    workspace_package: Option<&toml::Table>,
+    has_readme_file: bool,
+) -> (Vec<&'static str>, Vec<&'static str>) {
+    let has = |field: &str| match package.get(field) {
+        Some(toml::Value::Table(inherit)) if inherit.get("workspace").is_some() => {
+            workspace_package.is_some_and(|workspace| workspace.contains_key(field))
+        }
+        Some(_) => true,
+        None => false,
+    };
+    let required = ["description", "license"]
+        .into_iter()
+        .filter(|field| !(has(field) || *field == "license" && has("license-file")))
+        .collect();
+    let recommended = ["repository", "readme", "keywords", "categories"]
+        .into_iter()
+        .filter(|field| !(has(field) || *field == "readme" && has_readme_file))
+        .collect();
+    (required, recommended)
+}
+
+// Give path dependencies a version requirement next to their path, as crates.io needs; local
+// builds keep using the path. `versions` maps dependency names to the version to require, and
+// both inline tables and [dependencies.<name>] tables are rewritten
+fn add_path_dependency_versions(
+    manifest: &str,
+    versions: &std::collections::BTreeMap<String, String>,
+) -> String {
+    let is_dependencies = |section: &str| {
+        section == "dependencies"
+            || section == "build-dependencies"
+            || section.ends_with(".dependencies")
+            || section.ends_with(".build-dependencies")
+    };
+    let mut in_dependencies = false;
+    let mut dependency_table = None;
+    let mut lines: Vec<String> = Vec::new();
+    for line in manifest.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            let section = trimmed.trim_matches(['[', ']']).trim();
+            in_dependencies = is_dependencies(section);
+            dependency_table = section
+                .rsplit_once('.')
+                .filter(|(parent, _)| is_dependencies(parent))
+                .and_then(|(_, name)| versions.get(name.trim_matches(['"', '\''])));
+        } else if let Some(version) = dependency_table {
+            lines.push(line.to_string());
+            if trimmed.split(['=', ' ']).next() == Some("path") {
+                let indent = &line[..line.len() - line.trim_start().len()];
+                lines.push(format!("{}version = \"{}\"", indent, version));
+            }
+            continue;
+        } else if in_dependencies {
+            let name = trimmed.split(['=', ' ']).next().unwrap_or_default();
+            if let (Some(version), Some(table)) = (versions.get(name), trimmed.strip_suffix('}')) {
+                if table.contains("path") && !table.contains("version") {
+                    lines.push(format!(
+                        "{}, version = \"{}\" }}",
+                        line.trim_end().strip_suffix('}').unwrap_or(line).trim_end(),
+                        version
+                    ));
+                    continue;
+                }
+            }
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+// Move the version requirement of path dependencies on `crates` (workspace members being bumped)
+// to `version`, keeping any `=`, `^` or `~` operator, so a bump doesn't leave one member requiring
+// another's old version
+fn set_path_dependency_versions(
+    manifest: &str,
+    crates: &std::collections::BTreeSet<String>,
+    version: &str,
+) -> String {
+    let requirement = regex::Regex::new(r#"(\bversion\s*=\s*"[=^~]?)[^"]*""#).expect("valid regex");
+    let set_requirement = |line: &str| {
+        requirement
+            .replace(line, |captures: &regex::Captures| {
+                format!("{}{}\"", &captures[1], version)
+            })
+            .into_owned()
+    };
+    let is_dependencies = |section: &str| {
+        section == "dependencies"
+            || section == "build-dependencies"
+            || section == "dev-dependencies"
+            || section.ends_with(".dependencies")
+            || section.ends_with(".build-dependencies")
+            || section.ends_with(".dev-dependencies")
+    };
+    let mut in_dependencies = false;
+    let mut in_dependency_table = false;
+    let mut lines: Vec<String> = Vec::new();
+    for line in manifest.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            let section = trimmed.trim_matches(['[', ']']).trim();
+            in_dependencies = is_dependencies(section);
+            in_dependency_table = section.rsplit_once('.').is_some_and(|(parent, name)| {
+                is_dependencies(parent) && crates.contains(name.trim_matches(['"', '\'']))
+            });
+        } else if in_dependency_table && trimmed.split(['=', ' ']).next() == Some("version") {
+            lines.push(set_requirement(line));
+            continue;
+        } else if in_dependencies {
+            let name = trimmed.split(['=', ' ']).next().unwrap_or_default();
+            if crates.contains(name) && trimmed.ends_with('}') && trimmed.contains("path") {
+                lines.push(set_requirement(line));
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n") + "\n"
+}
+
+// Every normal and build dependency of a manifest, including target-specific ones
+fn manifest_dependencies(manifest: &toml::Table) -> impl Iterator<Item = (&String, &toml::Value)> {
+    const SECTIONS: [&str; 2] = ["dependencies", "build-dependencies"];
+    SECTIONS
+        .iter()
+        .filter_map(|section| manifest.get(*section))
+        .chain(
+            manifest
+                .get("target")
+                .and_then(|targets| targets.as_table())
+                .into_iter()
+                .flat_map(|targets| targets.values())
+                .flat_map(|target| SECTIONS.iter().filter_map(|section| target.get(*section))),
+        )
+        .filter_map(|dependencies| dependencies.as_table())
+        .flatten()
+}
+
+// A file `version bump` and `release` rewrite to set the project version
+struct VersionUpdate {
+    // Workspace member, "workspace" or a source file, for messages
+    what: String,
+    path: PathBuf,
+    contents: String,
+}
+
+// Point `const FIRMWARE_VERSION: &str = "..."` at a new version, so firmware that reports its
+// version outside of cargo (e.g. over a debug shell) keeps up with the crates
+fn set_firmware_version(source: &str, version: &str) -> String {
+    let constant =
+        regex::Regex::new(r#"(const\s+FIRMWARE_VERSION\s*:\s*&(?:'static\s+)?str\s*=\s*)"[^"]*""#)
+            .expect("valid regex");
+    constant
+        .replace_all(source, |captures: &regex::Captures| {
+            format!("{}\"{}\"", &captures[1], version)
+        })
+        .into_owned()
+}
+
+// Major, minor and patch of a version, ignoring any pre-release or build suffix
+fn version_numbers(version: &str) -> Option<[u64; 3]> {
+    let core = version.split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>().ok());
+    let numbers = [parts.next()??, parts.next()??, parts.next()??];
+    parts.next().is_none().then_some(numbers)
+}
+
+// The version a `version bump` ends at: the next major, minor or patch of `current`, or an
+// explicit version
+fn bumped_version(current: &str, to: &str) -> Option<String> {
+    let [major, minor, patch] = version_numbers(current)?;
+    match to {
+        "major" => Some(format!("{}.0.0", major + 1)),
+        "minor" => Some(format!("{}.{}.0", major, minor + 1)),
+        "patch" => Some(format!("{}.{}.{}", major, minor, patch + 1)),
+        explicit => parse_release_version(explicit).map(str::to_string),
+    }
+}
+
+// Start a keep-a-changelog section for `version`: right below [Unreleased] so the notes gathered
+// there become the release's, else above the newest release with `notes` as its entries
+fn add_changelog_section(changelog: &str, version: &str, date: &str, notes: &[String]) -> String {
+    let heading = format!("## [{}] - {}", version, date);
+    let mut lines: Vec<String> = changelog.lines().map(str::to_string).collect();
+    if lines.is_empty() {
+        lines.extend(["# Changelog".to_string(), String::new()]);
+    }
+    if let Some(at) = lines
+        .iter()
+        .position(|line| line.trim().eq_ignore_ascii_case("## [unreleased]"))
+    {
+        lines.splice(at + 1..at + 1, [String::new(), heading]);
+    } else {
+        let at = lines
+            .iter()
+            .position(|line| line.starts_with("## "))
+            .unwrap_or(lines.len());
+        let mut section = vec![heading, String::new()];
+        section.extend(notes.iter().map(|note| format!("- {}", note)));
+        if !notes.is_empty() {
+            section.push(String::new());
+        }
+        lines.splice(at..at, section);
+    }
+    lines.join("\n").trim_end().to_string() + "\n"
+}
+
+// Accept `v1.2.0` or `1.2.0` (with an optional pre-release or build suffix) and return it without the v
+fn parse_release_version(version: &str) -> Option<&str> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let core = version.split(['-', '+']).next()?;
+    let parts: Vec<&str> = core.split('.').collect();
+    (parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())))
+    .then_some(version)
+}
+
+impl MultiTargetTool {
+    // Files to rewrite so the project is at `version`: member manifests with their own version,
+    // then [workspace.package] for members inheriting it, then FIRMWARE_VERSION constants in
+    // member sources. Files already at the version are left out
+    fn version_updates(&self, version: &str) -> Result<Vec<VersionUpdate>, ToolError> {
+        let mut updates = Vec::new();
+        let members = self.workspace_members()?;
+        let mut manifests = Vec::new();
+        for member in &members {
+            let path = self.project_root.join(member).join("Cargo.toml");
+            if let Ok(manifest) = fs::read_to_string(&path) {
+                manifests.push((member, path, manifest));
+            }
+        }
+        let crates: std::collections::BTreeSet<String> = manifests
+            .iter()
+            .filter_map(|(_, _, manifest)| {
+                let manifest: toml::Table = manifest.parse().ok()?;
+                Some(manifest.get("package")?.get("name")?.as_str()?.to_string())
+            })
+            .collect();
+        for (member, path, manifest) in manifests {
+            let updated = set_path_dependency_versions(
+                &set_package_version(&manifest, "[package]", version),
+                &crates,
+                version,
+            );
+            if updated != manifest {
+                updates.push(VersionUpdate {
+                    what: member.clone(),
+                    path,
+                    contents: updated,
+                });
+            }
+        }
+        let root_path = self.project_root.join("Cargo.toml");
+        let root = fs::read_to_string(&root_path)?;
+        let updated = set_path_dependency_versions(
+            &set_package_version(&root, "[workspace.package]", version),
+            &crates,
+            version,
+        );
+        if updated != root {
+            updates.push(VersionUpdate {
+                what: "workspace".to_string(),
+                path: root_path,
+                contents: updated,
+            });
+        }
+        for member in &members {
+            let src = Path::new(member).join("src");
+            let Ok((_, files)) = walk_paths(&self.project_root.join(&src)) else {
+                continue;
+            };
+            for file in files
+                .iter()
+                .filter(|file| file.extension() == Some("rs".as_ref()))
+            {
+                let relative = src.join(file);
+                let path = self.project_root.join(&relative);
+                let source = fs::read_to_string(&path)?;
+                let updated = set_firmware_version(&source, version);
+                if updated != source {
+                    updates.push(VersionUpdate {
+                        what: relative.display().to_string(),
+                        path,
+                        contents: updated,
+                    });
+                }
+            }
+        }
+        Ok(updates)
+    }
+
+    // The highest version among the workspace members, and whether they all agree on it
+    fn project_version(&self) -> Result<(String, bool), ToolError> {
+        let root: toml::Table =
+            fs::read_to_string(self.project_root.join("Cargo.toml"))?.parse()?;
+        let workspace_version = root
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package"))
+            .and_then(|package| package.get("version"))
+            .and_then(|version| version.as_str());
+        let mut versions = Vec::new();
+        for member in self.workspace_members()? {
+            let Ok(manifest) =
+                fs::read_to_string(self.project_root.join(&member).join("Cargo.toml"))
+            else {
+                continue;
+            };
+            let manifest: toml::Table = manifest.parse()?;
+            let version = manifest
+                .get("package")
+                .and_then(|package| package.get("version"));
+            let version = match version {
+                Some(toml::Value::String(version)) => Some(version.clone()),
+                // version.workspace = true
+                Some(toml::Value::Table(_)) => workspace_version.map(str::to_string),
+                _ => None,
+            };
+            versions.extend(version.filter(|version| version_numbers(version).is_some()));
+        }
+        versions.sort_by_key(|version| version_numbers(version));
+        versions.dedup();
+        let consistent = versions.len() == 1;
+        let current = versions.pop().ok_or_else(|| {
+            ToolError::Config("No workspace member has a version like 1.2.0".to_string())
+        })?;
+        Ok((current, consistent))
+    }
+
+    pub(crate) fn handle_version_command(&self, cmd: VersionCommands) -> Result<(), ToolError> {
+        let VersionCommands::Bump { to, changelog } = cmd;
+        let (current, consistent) = self.project_version()?;
+        let version = bumped_version(&current, &to).ok_or_else(|| {
+            ToolError::Config(format!(
+                "'{}' is not major, minor, patch or a version like 1.2.0",
+                to
+            ))
+        })?;
+        if !consistent {
+            warn!(
+                "⚠️  The crates are at different versions; all of them move to {}",
+                version
+            );
+        }
+        info!("🔖 Version {} → {}", current, version);
+
+        let updates = self.version_updates(&version)?;
+        for update in &updates {
+            write_file(&update.path, &update.contents)?;
+        }
+        if updates.is_empty() {
+            info!("  ✓ Every crate is already at {}", version);
+        } else {
+            let changed: Vec<&str> = updates.iter().map(|update| update.what.as_str()).collect();
+            info!("  ✓ Set version {} in {}", version, changed.join(", "));
+        }
+        let lock_path = self.project_root.join("Cargo.lock");
+        if lock_path.exists() {
+            // Keep Cargo.lock in step, or --locked builds would refuse it
+            Journal::record_file(&lock_path);
+            self.cargo_metadata(None)?;
+            info!("  ✓ Updated Cargo.lock");
+        }
+
+        if changelog {
+            let path = self.project_root.join("CHANGELOG.md");
+            let existing = fs::read_to_string(&path).unwrap_or_default();
+            // Commit subjects since the last tag make a first draft of the entries
+            let notes: Vec<String> = self
+                .git(&["describe", "--tags", "--abbrev=0"])
+                .and_then(|tag| self.git(&["log", "--format=%s", &format!("{}..HEAD", tag)]))
+                .map(|log| log.lines().map(str::to_string).collect())
+                .unwrap_or_default();
+            let (year, month, day) = current_date();
+            let date = format!("{:04}-{:02}-{:02}", year, month, day);
+            write_file(
+                &path,
+                add_changelog_section(&existing, &version, &date, &notes),
+            )?;
+            info!("  ✓ Added a {} section to CHANGELOG.md", version);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn git(&self, args: &[&str]) -> Result<String, ToolError> {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.project_root)
+            .output()
+            .map_err(|e| ToolError::Other(format!("Could not run git: {}", e)))?;
+        if !output.status.success() {
+            return Err(ToolError::Other(format!(
+                "git {} failed:\n{}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    // Publish core-lib or drivers/<name>: complete metadata, a no_std build, version requirements on
+    // path dependencies and a clean `cargo package` come first, and --dry-run stops there
+    pub(crate) fn publish(
+        &self,
+        krate: &str,
+        dry_run: bool,
+        registry: Option<String>,
+        target: Option<&str>,
+    ) -> Result<(), ToolError> {
+        let config = self.load_glue_config()?;
+        let dir = match krate.trim_end_matches('/') {
+            "core-lib" => config
+                .core_dir()
+                .ok_or_else(|| {
+                    ToolError::Config(
+                        "core-lib comes from crates.io here; there is nothing to publish"
+                            .to_string(),
+                    )
+                })?
+                .to_string(),
+            driver if driver.starts_with("drivers/") => driver.to_string(),
+            _ => {
+                return Err(ToolError::Config(format!(
+                    "Can only publish core-lib or drivers/<name>, not '{}'",
+                    krate
+                )))
+            }
+        };
+        let crate_path = self.project_root.join(&dir);
+        let manifest_path = crate_path.join("Cargo.toml");
+        let manifest = fs::read_to_string(&manifest_path)
+            .map_err(|_| ToolError::Config(format!("No crate at {}", dir)))?;
+        let table: toml::Table = manifest.parse()?;
+        let root: toml::Table =
+            fs::read_to_string(self.project_root.join("Cargo.toml"))?.parse()?;
+        let workspace_package = root
+            .get("workspace")
+            .and_then(|workspace| workspace.get("package"))
+            .and_then(|package| package.as_table());
+        let package = table
+            .get("package")
+            .and_then(|package| package.as_table())
+            .ok_or_else(|| ToolError::Config(format!("{}/Cargo.toml has no [package]", dir)))?;
+        let name = package
+            .get("name")
+            .and_then(|name| name.as_str())
+            .ok_or_else(|| ToolError::Config(format!("{}/Cargo.toml has no package name", dir)))?
+            .to_string();
+        let registry = registry.or(config.publish.and_then(|publish| publish.registry));
+        info!(
+            "📦 Publishing {} to {}{}",
+            name,
+            registry.as_deref().unwrap_or("crates.io"),
+            if dry_run { " (dry run)" } else { "" }
+        );
+
+        if package.get("publish").and_then(|publish| publish.as_bool()) == Some(false) {
+            return Err(ToolError::Config(format!(
+                "{}/Cargo.toml sets publish = false; remove it to publish {}",
+                dir, name
+            )));
+        }
+        let (required, recommended) = missing_publish_metadata(
+            package,
+            workspace_package,
+            crate_path.join("README.md").exists(),
+        );
+        if !required.is_empty() {
+            return Err(ToolError::Config(format!(
+                "{} can't be published without {} in {}/Cargo.toml",
+                name,
+                required.join(" and "),
+                dir
+            )));
+        }
+        info!("  ✓ Package metadata is complete enough to publish");
+        if !recommended.is_empty() {
+            warn!(
+                "  ⚠️  Also worth setting for crates.io: {}",
+                recommended.join(", ")
+            );
+        }
+
+        let target = target.map(str::to_string).unwrap_or_else(|| {
+            config
+                .platforms
+                .iter()
+                .find(|p| p.target.starts_with("thumb"))
+                .map_or("thumbv7em-none-eabihf".to_string(), |p| p.target.clone())
+        });
+        let problems = self.no_std_purity_problems(&target, &name)?;
+        if !problems.is_empty() {
+            for problem in &problems {
+                error!("  ❌ {}", problem);
+            }
+            return Err(ToolError::Build(format!(
+                "{} does not build without std for {}",
+                name, target
+            )));
+        }
+        info!("  ✓ Builds without std for {}", target);
+
+        // Path dependencies need a version that already exists in the registry
+        let mut versions = std::collections::BTreeMap::new();
+        for (dependency, spec) in manifest_dependencies(&table) {
+            let Some(path) = spec.get("path").and_then(|path| path.as_str()) else {
+                continue;
+            };
+            if spec.get("version").is_some() {
+                continue;
+            }
+            let dependency_manifest: toml::Table =
+                fs::read_to_string(crate_path.join(path).join("Cargo.toml"))?.parse()?;
+            let version = match dependency_manifest
+                .get("package")
+                .and_then(|package| package.get("version"))
+            {
+                Some(toml::Value::String(version)) => Some(version.clone()),
+                Some(toml::Value::Table(_)) => workspace_package
+                    .and_then(|package| package.get("version"))
+                    .and_then(|version| version.as_str())
+                    .map(str::to_string),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                ToolError::Config(format!(
+                    "{} ({}) has no version to depend on",
+                    dependency, path
+                ))
+            })?;
+            info!(
+                "  ℹ️  {} {} is a path dependency; publish it first",
+                dependency, version
+            );
+            versions.insert(dependency.clone(), version);
+        }
+        let rewritten = !versions.is_empty();
+        if rewritten {
+            let updated = add_path_dependency_versions(&manifest, &versions);
+            let updated_table: toml::Table = updated.parse()?;
+            let unversioned: Vec<&str> = versions
+                .keys()
+                .filter(|dependency| {
+                    !manifest_dependencies(&updated_table)
+                        .any(|(name, spec)| name == *dependency && spec.get("version").is_some())
+                })
+                .map(String::as_str)
+                .collect();
+            if !unversioned.is_empty() {
+                return Err(ToolError::Config(format!(
+                    "Couldn't add a version requirement for {} in {}/Cargo.toml; add one next to \
+                     its path by hand",
+                    unversioned.join(", "),
+                    dir
+                )));
+            }
+            write_file(&manifest_path, updated)?;
+            info!(
+                "  ✓ Added version requirements to {}'s path dependencies",
+                name
+            );
+        }
+
+        // A dry run, or a failure before the upload, leaves the manifest as it was
+        Journal::record_file(&self.project_root.join("Cargo.lock"));
+        let result = self.package_and_publish(&name, registry.as_deref(), dry_run, rewritten);
+        if rewritten && (dry_run || result.is_err()) {
+            write_file(&manifest_path, &manifest)?;
+        }
+        result
+    }
+
+    // `cargo package` then, unless this is a dry run, `cargo publish` for one workspace crate
+    fn package_and_publish(
+        &self,
+        name: &str,
+        registry: Option<&str>,
+        dry_run: bool,
+        rewritten: bool,
+    ) -> Result<(), ToolError> {
+        let cargo = |subcommand: &str, allow_dirty: bool| {
+            let mut cmd = self.compile_command(&BuildTool::Cargo);
+            cmd.current_dir(&self.project_root)
+                .args([subcommand, "-p", name]);
+            if let Some(registry) = registry {
+                cmd.args(["--registry", registry]);
+            }
+            if allow_dirty {
+                cmd.arg("--allow-dirty");
+            }
+            run_logged(&mut cmd, true)
+        };
+        if !cargo("package", true)?.status.success() {
+            return Err(ToolError::Build(format!(
+                "cargo package failed for {}",
+                name
+            )));
+        }
+        info!("  ✓ Packaged {} and built it from the package", name);
+        if dry_run {
+            info!(
+                "✅ {} is ready to publish (dry run, nothing uploaded)",
+                name
+            );
+            return Ok(());
+        }
+        // The rewritten manifest is what gets published, committed or not
+        if !cargo("publish", rewritten)?.status.success() {
+            return Err(ToolError::Network(format!(
+                "cargo publish failed for {}",
+                name
+            )));
+        }
+        info!(
+            "✅ Published {} to {}",
+            name,
+            registry.unwrap_or("crates.io")
+        );
+        Ok(())
+    }
+
+    // Bump every member to the release version, commit it, build and test all platforms in
+    // release mode, collect the images with SBOMs and checksums, then tag the commit
+    pub(crate) fn release(
+        &self,
+        version: &str,
+        dry_run: bool,
+        sign: Option<&str>,
+        product: Option<&str>,
+    ) -> Result<(), ToolError> {
+        let version = parse_release_version(version).ok_or_else(|| {
+            ToolError::Config(format!("'{}' is not a version like v1.2.0", version))
+        })?;
+        let tag = match product {
+            Some(product) => format!("{}-v{}", product, version),
+            None => format!("v{}", version),
+        };
+        let config = self.load_glue_config()?;
+        let released: Vec<&Platform> = match product {
+            Some(product) => config.product_platforms(product)?,
+            None => config.platforms.iter().collect(),
+        };
+        let platforms: Vec<&str> = released.iter().map(|p| p.name.as_str()).collect();
+
+        // Tracked changes would end up in the release commit; new files are left alone
+        self.git(&["rev-parse", "--git-dir"]).map_err(|_| {
+            ToolError::Config("release needs the project to be a git repository".to_string())
+        })?;
+        if !self
+            .git(&["status", "--porcelain", "--untracked-files=no"])?
+            .is_empty()
+        {
+            return Err(ToolError::Config(
+                "The work tree has uncommitted changes; commit or stash them first".into(),
+            ));
+        }
+        if self.git(&["tag", "--list", &tag])? == tag {
+            return Err(ToolError::Config(format!("Tag {} already exists", tag)));
+        }
+
+        let bumps = self.version_updates(version)?;
+        let release_dir = Path::new("artifacts").join("release").join(&tag);
+
+        if dry_run {
+            info!("🔍 Release {} (dry run, nothing is changed)", tag);
+            let members: Vec<&str> = bumps.iter().map(|bump| bump.what.as_str()).collect();
+            if members.is_empty() {
+                info!("  1. Every crate is already at {}", version);
+            } else {
+                info!("  1. Set version {} in {}", version, members.join(", "));
+            }
+            info!("  2. Commit \"Release {}\"", tag);
+            if platforms.is_empty() {
+                info!("  3. Build the host crates with --release");
+            } else {
+                info!(
+                    "  3. Build the host crates and {} with --release",
+                    platforms.join(", ")
+                );
+            }
+            info!("  4. Run the host tests");
+            info!(
+                "  5. Collect images, an SBOM per platform and SHA256SUMS in {}",
+                release_dir.display()
+            );
+            match sign {
+                Some(key) => info!("  6. Sign SHA256SUMS with GPG key {}", key),
+                None => info!("  6. Leave SHA256SUMS unsigned (pass --sign <KEY_ID> to sign)"),
+            }
+            info!("  7. Tag the release commit {}", tag);
+            return Ok(());
+        }
+
+        info!("🚀 Releasing {}", tag);
+        for bump in &bumps {
+            write_file(&bump.path, &bump.contents)?;
+        }
+        if !bumps.is_empty() {
+            let members: Vec<&str> = bumps.iter().map(|bump| bump.what.as_str()).collect();
+            info!("  ✓ Set version {} in {}", version, members.join(", "));
+            // Refresh Cargo.lock so the commit carries the new member versions
+            self.cargo_metadata(None)?;
+            self.git(&[
+                "commit",
+                "--all",
+                "--quiet",
+                "-m",
+                &format!("Release {}", tag),
+            ])?;
+            info!("  ✓ Committed \"Release {}\"", tag);
+        }
+
+        let built = self.release_build(&released);
+        if let Err(e) = built {
+            if !bumps.is_empty() {
+                self.git(&["reset", "--keep", "HEAD~1"])?;
+                info!("  ↩️  Undid the release commit");
+            }
+            return Err(ToolError::Build(format!("Release {} aborted: {}", tag, e)));
+        }
+
+        let dir = self.project_root.join(&release_dir);
+        if dir.exists() {
+            remove_tree(&dir)?;
+        }
+        create_dirs(&dir)?;
+        let mut files = Vec::new();
+        for platform in &released {
+            let manifest = self.load_artifact_manifest(&platform.name, "release")?;
+            create_dirs(dir.join(&platform.name))?;
+            for artifact in &manifest.artifacts {
+                let file = Path::new(&platform.name).join(&artifact.name);
+                fs::copy(self.project_root.join(&artifact.path), dir.join(&file))?;
+                files.push(file);
+            }
+            let sbom = Path::new(&platform.name).join("sbom.cdx.json");
+            let components = self.write_sbom(platform, version, &dir.join(&sbom))?;
+            info!(
+                "  ✓ {}: {} image(s), SBOM with {} components",
+                platform.name,
+                manifest.artifacts.len(),
+                components
+            );
+            files.push(sbom);
+        }
+
+        use sha2::{Digest, Sha256};
+        let mut sums = String::new();
+        for file in &files {
+            let bytes = fs::read(dir.join(file))?;
+            sums.push_str(&format!(
+                "{:x}  {}\n",
+                Sha256::digest(&bytes),
+                file.to_string_lossy().replace('\\', "/")
+            ));
+        }
+        write_file(dir.join("SHA256SUMS"), sums)?;
+        info!("  ✓ Wrote {}", release_dir.join("SHA256SUMS").display());
+
+        if let Some(key) = sign {
+            let output = run_logged(
+                Command::new("gpg")
+                    .args([
+                        "--batch",
+                        "--yes",
+                        "--armor",
+                        "--detach-sign",
+                        "--local-user",
+                    ])
+                    .arg(key)
+                    .arg("SHA256SUMS")
+                    .current_dir(&dir),
+                true,
+            )
+            .map_err(|e| ToolError::Other(format!("Could not run gpg: {}", e)))?;
+            if !output.status.success() {
+                return Err(ToolError::Other(format!(
+                    "gpg could not sign SHA256SUMS with key {}",
+                    key
+                )));
+            }
+            info!(
+                "  ✓ Signed {}",
+                release_dir.join("SHA256SUMS.asc").display()
+            );
+        }
+
+        self.git(&["tag", "--annotate", &tag, "-m", &format!("Release {}", tag)])?;
+        info!("  ✓ Tagged {}", tag);
+        info!("✅ Released {}", tag);
+        info!("  Publish it with: git push --follow-tags");
+        Ok(())
+    }
+
+    fn release_build(&self, platforms: &[&Platform]) -> Result<(), ToolError> {
+        let bar = progress_bar(Some(platforms.len() as u64 + 1), "host");
+        bar.set_prefix("Building ");
+        self.build(None, false, true, false, None, &[])?;
+        for platform in platforms {
+            bar.inc(1);
+            bar.set_message(platform.name.clone());
+            self.build(Some(platform.name.clone()), false, true, false, None, &[])?;
+        }
+        bar.finish_and_clear();
+        self.test(None, None, false, None, &[])
+    }
+
+    // CycloneDX SBOM of the crates a platform's image is built from
+    fn write_sbom(
+        &self,
+        platform: &Platform,
+        version: &str,
+        path: &Path,
+    ) -> Result<usize, ToolError> {
+        let metadata = self.cargo_metadata(Some(&platform.target))?;
+        let shipped = Self::reachable_packages(&metadata, &platform_crates(platform));
+        let members: Vec<&str> = metadata["workspace_members"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|id| id.as_str())
+            .collect();
+        let mut components = Vec::new();
+        for package in metadata["packages"].as_array().into_iter().flatten() {
+            let id = package["id"].as_str().unwrap_or_default();
+            let name = package["name"].as_str().unwrap_or_default();
+            let package_version = package["version"].as_str().unwrap_or_default();
+            if members.contains(&id) || !shipped.contains(&format!("{} {}", name, package_version))
+            {
+                continue;
+            }
+            let mut component = serde_json::json!({
+                "type": "library",
+                "name": name,
+                "version": package_version,
+                "purl": format!("pkg:cargo/{}@{}", name, package_version),
+            });
+            if let Some(license) = package["license"].as_str() {
+                component["licenses"] = serde_json::json!([{ "expression": license }]);
+            }
+            components.push(component);
+        }
+        let count = components.len();
+        let sbom = serde_json::json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.5",
+            "version": 1,
+            "metadata": {
+                "component": {
+                    "type": "firmware",
+                    "name": format!("app-{}", platform.name),
+                    "version": version,
+                },
+            },
+            "components": components,
+        });
+        write_file(path, serde_json::to_string_pretty(&sbom)? + "\n")?;
+        Ok(count)
+    }
+}
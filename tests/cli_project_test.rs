@@ -193,6 +193,14 @@ fn test_generated_tests_work() {
         .arg("test")
         .assert()
         .success();
+
+    // The host tests don't need glue.toml
+    fs::remove_file(temp.path().join("testproj/glue.toml")).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(temp.path().join("testproj"))
+        .arg("test")
+        .assert()
+        .success();
 }
 
 /// Test glue config validation
@@ -449,3 +457,7051 @@ fn test_complete_workflow_success_criterion() {
 
     println!("✅ Success criterion met: Complete workflow executed without manual config editing");
 }
+
+/// Test that a bootloader crate and partitioned memory layout are generated
+#[test]
+fn test_add_platform_with_bootloader() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(temp.path().join("testproj"))
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--with-bootloader")
+        .arg("embassy-boot")
+        .arg("--chip")
+        .arg("STM32F411CEUx")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let boot_main = fs::read_to_string(project_path.join("boot-stm32/src/main.rs")).unwrap();
+    assert!(boot_main.contains("BootLoader::prepare::<_, _, _, 2048>(config)"));
+    assert!(boot_main.contains("bl.load(FLASH_BASE as u32 + active_offset)"));
+    assert!(!boot_main.contains("loop {}"));
+    assert!(
+        fs::read_to_string(project_path.join("boot-stm32/Cargo.toml"))
+            .unwrap()
+            .contains("embassy-stm32 = { version = \"0.2\", features = [\"stm32f411ce\"] }")
+    );
+
+    let boot_memory = fs::read_to_string(project_path.join("boot-stm32/memory.x")).unwrap();
+    assert!(boot_memory.contains("FLASH : ORIGIN = 0x08000000, LENGTH = 24K"));
+    assert!(boot_memory.contains("RAM : ORIGIN = 0x20000000, LENGTH = 128K"));
+    assert!(boot_memory.contains("__bootloader_active_start"));
+
+    let app_memory = fs::read_to_string(project_path.join("app-stm32/memory.x")).unwrap();
+    assert!(
+        app_memory.contains("FLASH : ORIGIN = 0x08007000"),
+        "App should be linked into the active slot"
+    );
+
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"boot-stm32\""));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("kind = \"embassy-boot\""));
+    assert!(glue.contains("name = \"DFU\""));
+
+    // nRF flash starts at 0 and is driven through the NVMC
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "nrf", "--target", "thumbv7em-none-eabihf"])
+        .args([
+            "--with-bootloader",
+            "embassy-boot",
+            "--chip",
+            "nRF52840_xxAA",
+        ])
+        .assert()
+        .success();
+    assert!(
+        fs::read_to_string(project_path.join("boot-nrf/src/main.rs"))
+            .unwrap()
+            .contains("Nvmc::new(p.NVMC)")
+    );
+    let boot_memory = fs::read_to_string(project_path.join("boot-nrf/memory.x")).unwrap();
+    assert!(boot_memory.contains("FLASH : ORIGIN = 0x00000000, LENGTH = 24K"));
+    assert!(boot_memory.contains("RAM : ORIGIN = 0x20000000, LENGTH = 256K"));
+
+    // A chip whose RAM isn't known gets no bootloader rather than a guessed memory.x
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "odd", "--target", "thumbv7em-none-eabihf"])
+        .args([
+            "--with-bootloader",
+            "embassy-boot",
+            "--chip",
+            "STM32X999ZZTx",
+        ])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "Don't know the RAM layout of 'odd' for its bootloader",
+        ));
+    assert!(!project_path.join("boot-odd").exists());
+    assert!(!fs::read_to_string(project_path.join("glue.toml"))
+        .unwrap()
+        .contains("name = \"odd\""));
+
+    // Without a chip there is no flash driver to wire
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "other", "--target", "thumbv7em-none-eabihf"])
+        .args(["--with-bootloader", "embassy-boot"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("pass --chip"));
+    assert!(!project_path.join("boot-other").exists());
+
+    // Bootloaders only make sense for embedded targets
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(temp.path().join("testproj"))
+        .arg("add-platform")
+        .arg("linux")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--with-bootloader")
+        .arg("mcuboot")
+        .assert()
+        .failure();
+}
+
+/// Test scaffolding a hardware-agnostic driver crate
+#[test]
+fn test_new_driver() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(temp.path().join("testproj"))
+        .arg("new-driver")
+        .arg("bme280")
+        .arg("--bus")
+        .arg("i2c")
+        .assert()
+        .success();
+
+    let driver_path = temp.path().join("testproj/drivers/bme280");
+    let lib_content = fs::read_to_string(driver_path.join("src/lib.rs")).unwrap();
+    assert!(
+        lib_content.contains("#![no_std]"),
+        "Driver should be no_std"
+    );
+    assert!(lib_content.contains("pub struct Bme280<I2C>"));
+
+    let test_content = fs::read_to_string(driver_path.join("tests/bme280.rs")).unwrap();
+    assert!(test_content.contains("embedded_hal_mock::eh1::i2c"));
+
+    let workspace = fs::read_to_string(temp.path().join("testproj/Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"drivers/bme280\""));
+
+    // Creating the same driver twice should fail
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(temp.path().join("testproj"))
+        .arg("new-driver")
+        .arg("bme280")
+        .assert()
+        .failure();
+}
+
+/// Test board support crate generation from glue.toml resources
+#[test]
+fn test_bsp_new_and_generate() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bsp")
+        .arg("new")
+        .arg("nucleo")
+        .arg("--platform")
+        .arg("stm32")
+        .arg("--resource")
+        .arg("LED1=hal::gpio::PA5<hal::gpio::Output>")
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("[[boards]]"));
+    assert!(glue.contains("name = \"LED1\""));
+
+    // Fill in the resource initializer and regenerate
+    let glue = glue.replace(
+        "name = \"LED1\"",
+        "name = \"LED1\"\ninit = \"gpioa.pa5.into_push_pull_output()\"",
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bsp")
+        .arg("generate")
+        .arg("nucleo")
+        .assert()
+        .success();
+
+    let lib_content = fs::read_to_string(project_path.join("bsp-nucleo/src/lib.rs")).unwrap();
+    assert!(lib_content.contains("pub type Led1 = hal::gpio::PA5<hal::gpio::Output>;"));
+    assert!(lib_content.contains("led1: gpioa.pa5.into_push_pull_output(),"));
+    assert!(lib_content.contains("pub fn init() -> Self"));
+
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"bsp-nucleo\""));
+
+    // Boards must reference a known platform
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bsp")
+        .arg("new")
+        .arg("other")
+        .arg("--platform")
+        .arg("missing")
+        .assert()
+        .failure();
+}
+
+/// Test per-platform cargo config generation and glue sync
+#[test]
+fn test_platform_cargo_config_sync() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let config_path = project_path.join("app-stm32/.cargo/config.toml");
+    let cargo_config = fs::read_to_string(&config_path).unwrap();
+    assert!(cargo_config.contains("[target.thumbv7em-none-eabihf]"));
+    assert!(cargo_config.contains("link-arg=-Tlink.x"));
+
+    // Change the platform's linker in glue.toml and sync
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    let glue = glue.replace(
+        "target = \"thumbv7em-none-eabihf\"",
+        "target = \"thumbv7em-none-eabihf\"\nlinker = \"flip-link\"",
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success();
+
+    let cargo_config = fs::read_to_string(&config_path).unwrap();
+    assert!(cargo_config.contains("linker = \"flip-link\""));
+}
+
+/// Test that a per-platform runner is recorded and written to the cargo config
+#[test]
+fn test_platform_runner() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--runner")
+        .arg("probe-rs run --chip STM32F411RETx")
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("runner = \"probe-rs run --chip STM32F411RETx\""));
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-stm32/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("runner = \"probe-rs run --chip STM32F411RETx\""));
+
+    // Embedded platforms without a runner can't be run
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("nrf52")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("run")
+        .arg("--target")
+        .arg("nrf52")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No runner configured"));
+}
+
+/// Test GitHub Actions workflow generation from glue.toml platforms
+#[test]
+fn test_ci_generate_github() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("ci")
+        .arg("generate")
+        .arg("github")
+        .assert()
+        .success();
+
+    let workflow = fs::read_to_string(project_path.join(".github/workflows/firmware.yml")).unwrap();
+    assert!(workflow.contains("cargo test --workspace"));
+    assert!(workflow.contains("- platform: stm32"));
+    assert!(workflow.contains("target: thumbv7em-none-eabihf"));
+    assert!(workflow.contains("actions/upload-artifact@v4"));
+}
+
+/// Test Dockerfile and devcontainer generation from glue.toml platforms
+#[test]
+fn test_env_generate_docker() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("env")
+        .arg("generate")
+        .arg("docker")
+        .arg("--toolchain")
+        .arg("1.80.0")
+        .assert()
+        .success();
+
+    let dockerfile = fs::read_to_string(project_path.join("Dockerfile")).unwrap();
+    assert!(dockerfile.contains("FROM rust:1.80.0-bookworm"));
+    assert!(dockerfile.contains("rustup target add thumbv7em-none-eabihf"));
+    assert!(dockerfile.contains("probe-rs-tools"));
+
+    let devcontainer =
+        fs::read_to_string(project_path.join(".devcontainer/devcontainer.json")).unwrap();
+    assert!(devcontainer.contains("\"dockerfile\": \"../Dockerfile\""));
+
+    // There is no nightly image, so a pinned nightly goes on top of the stable one
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "env",
+            "generate",
+            "docker",
+            "--toolchain",
+            "nightly-2024-05-01",
+        ])
+        .assert()
+        .success();
+    let dockerfile = fs::read_to_string(project_path.join("Dockerfile")).unwrap();
+    assert!(dockerfile.contains("FROM rust:1-bookworm"));
+    assert!(dockerfile.contains(
+        "RUN rustup toolchain install nightly-2024-05-01 --profile minimal \\\n && rustup default nightly-2024-05-01"
+    ));
+}
+
+/// Test Nix flake generation with per-platform extras
+#[test]
+fn test_env_generate_nix() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("esp32c3")
+        .arg("--target")
+        .arg("riscv32imc-unknown-none-elf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("env")
+        .arg("generate")
+        .arg("nix")
+        .arg("--toolchain")
+        .arg("1.80.0")
+        .assert()
+        .success();
+
+    let flake = fs::read_to_string(project_path.join("flake.nix")).unwrap();
+    assert!(flake.contains("pkgs.rust-bin.stable.\"1.80.0\".default"));
+    assert!(flake.contains("targets = [ \"riscv32imc-unknown-none-elf\" ];"));
+    assert!(flake.contains("pkgs.probe-rs-tools"));
+    assert!(flake.contains("pkgs.espflash"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "env",
+            "generate",
+            "nix",
+            "--toolchain",
+            "nightly-2024-05-01",
+        ])
+        .assert()
+        .success();
+    let flake = fs::read_to_string(project_path.join("flake.nix")).unwrap();
+    assert!(flake.contains("pkgs.rust-bin.nightly.\"2024-05-01\".default"));
+}
+
+/// Test VSCode debug configuration generation keeps user entries
+#[test]
+fn test_ide_vscode() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--runner")
+        .arg("probe-rs run --chip STM32F411RETx")
+        .assert()
+        .success();
+
+    fs::create_dir_all(project_path.join(".vscode")).unwrap();
+    fs::write(
+        project_path.join(".vscode/launch.json"),
+        r#"{"version": "0.2.0", "configurations": [{"name": "Mine", "type": "lldb"}]}"#,
+    )
+    .unwrap();
+
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("ide")
+            .arg("vscode")
+            .assert()
+            .success();
+    }
+
+    let launch = fs::read_to_string(project_path.join(".vscode/launch.json")).unwrap();
+    assert!(launch.contains("\"name\": \"Mine\""));
+    assert_eq!(launch.matches("\"Debug app-stm32\"").count(), 1);
+    assert!(launch.contains("\"type\": \"probe-rs-debug\""));
+    assert!(launch.contains("\"chip\": \"STM32F411RETx\""));
+    assert!(launch.contains("target/thumbv7em-none-eabihf/debug/stm32"));
+    assert!(launch.contains("\"preLaunchTask\": \"build app-stm32\""));
+
+    let tasks = fs::read_to_string(project_path.join(".vscode/tasks.json")).unwrap();
+    assert!(tasks.contains("\"label\": \"build app-stm32\""));
+}
+
+/// Test the no_std audit flags std-only dependencies of core-lib
+#[test]
+fn test_audit_no_std() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("audit")
+        .arg("no-std")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No std dependencies found"));
+
+    // Pull a std-only crate into core-lib
+    let manifest_path = project_path.join("core-lib/Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap().replace(
+        "embedded-hal = { workspace = true }",
+        "embedded-hal = { workspace = true }\nembedded-hal-mock = { workspace = true }",
+    );
+    fs::write(&manifest_path, manifest).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("audit")
+        .arg("no-std")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "embedded-hal-mock does not declare no_std",
+        ))
+        .stdout(predicate::str::contains(
+            "core-lib v0.1.0 → embedded-hal-mock",
+        ));
+}
+
+/// Test security advisories are attributed to the crates that ship them
+#[test]
+fn test_audit_security() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    // A local advisory database with one shipped and one dev-only vulnerable crate
+    let db = temp.path().join("advisory-db");
+    for (id, package, versions, title) in [
+        (
+            "RUSTSEC-2024-0001",
+            "embedded-hal",
+            "patched = [\">= 9.0.0\"]\nunaffected = [\"< 1.0.0\"]",
+            "Shipped",
+        ),
+        (
+            "RUSTSEC-2024-0002",
+            "embedded-hal-mock",
+            "patched = []",
+            "Dev only",
+        ),
+    ] {
+        let dir = db.join("crates").join(package);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join(format!("{}.md", id)),
+            format!(
+                "```toml\n[advisory]\nid = \"{}\"\npackage = \"{}\"\ndate = \"2024-01-01\"\n\n\
+                 [versions]\n{}\n```\n\n# {}\n\nDescription.\n",
+                id, package, versions, title
+            ),
+        )
+        .unwrap();
+    }
+
+    // The audit reads Cargo.lock as-is rather than generating one
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["audit", "security", "--db"])
+        .arg(&db)
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Cargo.lock is missing"));
+    assert!(!project_path.join("Cargo.lock").exists());
+
+    std::process::Command::new("cargo")
+        .arg("generate-lockfile")
+        .current_dir(&project_path)
+        .status()
+        .unwrap();
+    let lock = fs::read_to_string(project_path.join("Cargo.lock")).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    let output = cmd
+        .current_dir(&project_path)
+        .args(["audit", "security", "--json", "--db"])
+        .arg(&db)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report[0]["scope"], "core-lib");
+    let advisories = report[0]["advisories"].as_array().unwrap();
+    assert_eq!(advisories.len(), 1);
+    assert_eq!(advisories[0]["id"], "RUSTSEC-2024-0001");
+    assert_eq!(advisories[0]["title"], "Shipped");
+    assert_eq!(advisories[0]["version"], "1.0.0");
+    assert_eq!(
+        fs::read_to_string(project_path.join("Cargo.lock")).unwrap(),
+        lock
+    );
+
+    // Without a local copy the database is fetched, and an unreachable one is a network error
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("CARGO_HOME", temp.path().join("cargo-home"))
+        .env("HTTPS_PROXY", "http://127.0.0.1:9")
+        .env("https_proxy", "http://127.0.0.1:9")
+        .env_remove("NO_PROXY")
+        .env_remove("no_proxy")
+        .args(["audit", "security"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains(
+            "Could not fetch the RustSec advisory database",
+        ));
+}
+
+/// Test the license report enforces the glue.toml policy
+#[test]
+fn test_licenses_policy() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("licenses")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("MIT OR Apache-2.0"));
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[license_policy]\ndeny = [\"MIT\", \"Apache-*\"]\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("licenses")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "embedded-hal 1.0.0: MIT OR Apache-2.0 (denied by license_policy)",
+        ));
+
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[license_policy]\ndeny = [\"MIT\", \"Apache-*\"]\nexceptions = [\"embedded-hal\"]\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("licenses")
+        .assert()
+        .success();
+}
+
+/// Test deps add edits core-lib and rejects std-only crates
+#[test]
+fn test_deps_add() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let manifest_path = project_path.join("core-lib/Cargo.toml");
+    let original = fs::read_to_string(&manifest_path).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("deps")
+        .arg("add")
+        .arg("embedded-hal-mock")
+        .arg("--core")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "embedded-hal-mock does not declare no_std",
+        ));
+    assert_eq!(fs::read_to_string(&manifest_path).unwrap(), original);
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("deps")
+        .arg("add")
+        .arg("embedded-hal-mock")
+        .arg("--core")
+        .arg("--force")
+        .assert()
+        .success();
+
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    assert!(manifest.contains("embedded-hal-mock.workspace = true"));
+}
+
+/// Test glue update requires a recorded HAL source
+#[test]
+fn test_glue_update_without_source() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("update")
+        .arg("stm32")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no recorded HAL source"));
+}
+
+/// Test check covers host crates and every configured platform
+#[test]
+fn test_check_all() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("check")
+        .arg("--all")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Checking host crates"))
+        .stdout(predicate::str::contains(
+            "Checking platform: desk (x86_64-unknown-linux-gnu)",
+        ))
+        .stdout(predicate::str::contains("Check passed"));
+}
+
+/// Test add-platform wires a heap allocator into the app and memory.x
+#[test]
+fn test_add_platform_with_alloc() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let lib_path = project_path.join("core-lib/src/lib.rs");
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    fs::write(&lib_path, format!("//! Shared logic\n{}", lib)).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--alloc")
+        .arg("embedded-alloc:8K")
+        .assert()
+        .success();
+
+    let memory = fs::read_to_string(project_path.join("app-stm32/memory.x")).unwrap();
+    assert!(memory.contains("RAM : ORIGIN = 0x20000000, LENGTH = 56K"));
+    assert!(memory.contains("HEAP : ORIGIN = 0x2000e000, LENGTH = 8K"));
+    assert!(memory.contains("_heap_start = ORIGIN(HEAP);"));
+
+    let app_toml = fs::read_to_string(project_path.join("app-stm32/Cargo.toml")).unwrap();
+    assert!(app_toml.contains("features = [\"alloc\"]"));
+    assert!(app_toml.contains("embedded-alloc = \"0.6\""));
+    // The heap is locked with a critical section, whatever the HAL needs
+    assert!(app_toml.contains(
+        "cortex-m = { version = \"0.7\", features = [\"critical-section-single-core\"] }"
+    ));
+
+    let main_rs = fs::read_to_string(project_path.join("app-stm32/src/main.rs")).unwrap();
+    assert!(main_rs.contains("#[global_allocator]"));
+    assert!(main_rs.contains("HEAP.init(start, end - start);"));
+
+    let core_toml = fs::read_to_string(project_path.join("core-lib/Cargo.toml")).unwrap();
+    assert!(core_toml.contains("alloc = []"));
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    assert!(lib.starts_with(
+        "//! Shared logic\n#![cfg_attr(not(feature = \"std\"), no_std)]\n\n#[cfg(feature = \"alloc\")]\nextern crate alloc;\n"
+    ));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("kind = \"embedded-alloc\""));
+    assert!(glue.contains("heap_size = 8192"));
+}
+
+/// Test selectable panic strategies and the panic-persist RAM region
+#[test]
+fn test_add_platform_panic_strategy() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    for (name, panic) in [("persist", "persist"), ("custom", "custom")] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("add-platform")
+            .arg(name)
+            .arg("--target")
+            .arg("thumbv7em-none-eabihf")
+            .arg("--panic")
+            .arg(panic)
+            .assert()
+            .success();
+    }
+
+    let app_toml = fs::read_to_string(project_path.join("app-persist/Cargo.toml")).unwrap();
+    assert!(app_toml.contains("panic-persist = \"0.3\""));
+    assert!(!app_toml.contains("panic-halt"));
+    let memory = fs::read_to_string(project_path.join("app-persist/memory.x")).unwrap();
+    assert!(memory.contains("RAM : ORIGIN = 0x20000000, LENGTH = 63K"));
+    assert!(memory.contains("PANDUMP : ORIGIN = 0x2000fc00, LENGTH = 1K"));
+    assert!(memory.contains("_panic_dump_start = ORIGIN(PANDUMP);"));
+    let main_rs = fs::read_to_string(project_path.join("app-persist/src/main.rs")).unwrap();
+    assert!(main_rs.contains("use panic_persist as _;"));
+    assert!(main_rs.contains("panic_persist::get_panic_message_bytes()"));
+
+    let app_toml = fs::read_to_string(project_path.join("app-custom/Cargo.toml")).unwrap();
+    assert!(!app_toml.contains("panic-"));
+    let main_rs = fs::read_to_string(project_path.join("app-custom/src/main.rs")).unwrap();
+    assert!(main_rs.contains("#[panic_handler]"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("panic = \"persist\""));
+    assert!(glue.contains("panic = \"custom\""));
+}
+
+/// Test partition layouts drive memory.x, core-lib constants and ESP tables
+#[test]
+fn test_partitions_generate() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    // A crate doc and a second inner attribute, which the module must not be put above
+    let lib_path = project_path.join("core-lib/src/lib.rs");
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    fs::write(
+        &lib_path,
+        lib.replacen(
+            "#![cfg_attr(not(feature = \"std\"), no_std)]\n",
+            "//! Shared logic\n#![cfg_attr(not(feature = \"std\"), no_std)]\n#![deny(unsafe_code)]\n",
+            1,
+        ),
+    )
+    .unwrap();
+
+    for (name, target) in [
+        ("stm32", "thumbv7em-none-eabihf"),
+        ("esp32", "xtensa-esp32-none-elf"),
+    ] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("add-platform")
+            .arg(name)
+            .arg("--target")
+            .arg(target)
+            .assert()
+            .success();
+
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("partitions")
+            .arg("generate")
+            .arg(name)
+            .assert()
+            .success();
+    }
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("[[platforms.partitions]]"));
+    assert!(glue.contains("kind = \"nvs\""));
+
+    let memory = fs::read_to_string(project_path.join("app-stm32/memory.x")).unwrap();
+    assert!(memory.contains("FLASH : ORIGIN = 0x08000000, LENGTH = 224K"));
+    assert!(memory.contains("NVS : ORIGIN = 0x08038000, LENGTH = 16K"));
+
+    let constants = fs::read_to_string(project_path.join("core-lib/src/partitions.rs")).unwrap();
+    assert!(constants.contains("pub mod stm32 {"));
+    assert!(constants.contains("pub const NVS_START: u32 = 0x08038000;"));
+    assert!(constants.contains("pub mod esp32 {"));
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    assert!(lib.contains("#![deny(unsafe_code)]\n\npub mod partitions;\n"));
+
+    let csv = fs::read_to_string(project_path.join("app-esp32/partitions.csv")).unwrap();
+    assert!(csv.contains("nvs, data, nvs, 0x9000, 0x6000,"));
+    assert!(csv.contains("factory, app, factory, 0x10000, 0x100000,"));
+
+    // Overlapping partitions are rejected
+    let overlapping = glue.replacen("length = 229376", "length = 262144", 1);
+    fs::write(project_path.join("glue.toml"), overlapping).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("partitions")
+        .arg("generate")
+        .arg("stm32")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Partitions APP and NVS overlap"));
+
+    // So is one whose end doesn't fit in 32 bits
+    let overflowing = glue.replacen("origin = 134463488", "origin = 4294963200", 1);
+    fs::write(project_path.join("glue.toml"), overflowing).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["partitions", "generate", "stm32"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "Partition USER_DATA (0xfffff000 + 0x4000) runs past the end",
+        ));
+}
+
+/// Test the no_std purity check points at std usage in core-lib
+#[test]
+fn test_check_no_std_purity() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    let output = cmd
+        .current_dir(&project_path)
+        .arg("check")
+        .arg("no-std-purity")
+        .output()
+        .unwrap();
+    if !output.status.success() {
+        // Without the bare-metal target installed the check can only explain how to get it
+        assert!(String::from_utf8_lossy(&output.stderr).contains("rustup target add"));
+        return;
+    }
+    assert!(String::from_utf8_lossy(&output.stdout).contains("core-lib is no_std clean"));
+
+    let lib_path = project_path.join("core-lib/src/lib.rs");
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    fs::write(
+        &lib_path,
+        format!(
+            "{}\npub fn names() -> std::vec::Vec<u8> {{\n    std::vec::Vec::new()\n}}\n",
+            lib
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("check")
+        .arg("no-std-purity")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("core-lib/src/lib.rs:"))
+        .stdout(predicate::str::contains(
+            "pub fn names() -> std::vec::Vec<u8> {",
+        ));
+}
+
+/// Test async vs blocking trait flavors are surfaced by glue list and validate
+#[test]
+fn test_glue_trait_flavors() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    fs::write(
+        project_path.join("glue.toml"),
+        r#"[[platforms]]
+name = "blocking"
+target = "thumbv7em-none-eabihf"
+features = []
+
+[platforms.hal_info]
+source = "https://github.com/example/blocking-hal"
+required_traits = []
+mocked_traits = ["I2c"]
+warnings = []
+
+[[platforms.hal_info.provided_traits]]
+name = "I2c"
+module = "lib"
+implemented_types = ["I2c0"]
+native_mockable = true
+
+[[platforms]]
+name = "async"
+target = "thumbv7em-none-eabihf"
+features = []
+
+[platforms.hal_info]
+source = "https://github.com/example/async-hal"
+required_traits = []
+mocked_traits = ["I2c", "I2c"]
+warnings = []
+
+[[platforms.hal_info.provided_traits]]
+name = "I2c"
+module = "lib"
+implemented_types = ["I2c0"]
+native_mockable = true
+flavor = "blocking"
+
+[[platforms.hal_info.provided_traits]]
+name = "I2c"
+module = "lib"
+implemented_types = ["I2c0"]
+native_mockable = true
+flavor = "async"
+"#,
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 blocking, 0 async"))
+        .stdout(predicate::str::contains("1 blocking, 1 async"));
+
+    // An async core-lib needs a HAL with async traits
+    let manifest_path = project_path.join("core-lib/Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap().replace(
+        "[dependencies]\n",
+        "[dependencies]\nembedded-hal-async = \"1.0\"\n",
+    );
+    fs::write(&manifest_path, manifest).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("validate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("async traits: I2c"))
+        .stderr(predicate::str::contains(
+            "core-lib uses embedded-hal-async but this HAL has no async traits",
+        ));
+}
+
+/// Test add-platform --simulator creates a host app that replays a scenario file
+#[test]
+fn test_add_platform_simulator() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("sim")
+        .arg("--simulator")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Simulator 'sim' added successfully",
+        ));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("kind = \"simulator\""));
+    assert!(project_path.join("app-sim/scenario.txt").exists());
+    assert!(!project_path.join("hal-sim").exists());
+
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"app-sim\""));
+    assert!(!workspace.contains("\"hal-sim\""));
+
+    fs::write(
+        project_path.join("app-sim/scenario.txt"),
+        "temp 0x0010\ntick 1000\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("run")
+        .arg("--target")
+        .arg("sim")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[sensor] temperature = 16"))
+        .stdout(predicate::str::contains("[led] on"));
+}
+
+/// Test add-platform --kind linux-embedded wraps linux-embedded-hal for SBCs
+#[test]
+fn test_add_platform_linux_embedded() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("rpi")
+        .arg("--target")
+        .arg("aarch64-unknown-linux-gnu")
+        .arg("--kind")
+        .arg("linux-embedded")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Created Linux HAL wrapper: hal-rpi",
+        ));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("kind = \"linux-embedded\""));
+    assert!(glue.contains("hal_crate = \"linux-embedded-hal\""));
+
+    let hal_manifest = fs::read_to_string(project_path.join("hal-rpi/Cargo.toml")).unwrap();
+    assert!(hal_manifest.contains("linux-embedded-hal = \"0.4\""));
+    let hal_lib = fs::read_to_string(project_path.join("hal-rpi/src/lib.rs")).unwrap();
+    assert!(hal_lib.contains("pub fn open_led"));
+    assert!(hal_lib.contains("pub fn open_i2c"));
+    assert!(!hal_lib.contains("#![no_std]"));
+
+    let app_main = fs::read_to_string(project_path.join("app-rpi/src/main.rs")).unwrap();
+    assert!(app_main.contains("hal_rpi::open_led"));
+    assert!(!project_path.join("app-rpi/memory.x").exists());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("mcu")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--kind")
+        .arg("linux-embedded")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs a Linux target triple"));
+}
+
+/// Test add-platform --preset sets up a RISC-V chip with riscv-rt instead of cortex-m-rt
+#[test]
+fn test_add_platform_riscv_preset() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("gd32")
+        .arg("--preset")
+        .arg("gd32vf103")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("riscv32imac-unknown-none-elf"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("hal_crate = \"gd32vf103xx-hal\""));
+    assert!(glue.contains("chip = \"GD32VF103CBT6\""));
+
+    let app_manifest = fs::read_to_string(project_path.join("app-gd32/Cargo.toml")).unwrap();
+    assert!(app_manifest.contains("riscv-rt"));
+    assert!(!app_manifest.contains("cortex-m-rt"));
+    let app_main = fs::read_to_string(project_path.join("app-gd32/src/main.rs")).unwrap();
+    assert!(app_main.contains("use riscv_rt::entry;"));
+
+    let memory_x = fs::read_to_string(project_path.join("app-gd32/memory.x")).unwrap();
+    assert!(memory_x.contains("FLASH : ORIGIN = 0x08000000, LENGTH = 128K"));
+    assert!(memory_x.contains("RAM : ORIGIN = 0x20000000, LENGTH = 32K"));
+    assert!(memory_x.contains("REGION_ALIAS(\"REGION_TEXT\", FLASH);"));
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-gd32/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("link-arg=-Tmemory.x"));
+
+    // esp-hal brings its own linker scripts
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("c3")
+        .arg("--preset")
+        .arg("esp32c3")
+        .assert()
+        .success();
+    assert!(!project_path.join("app-c3/memory.x").exists());
+    let cargo_config = fs::read_to_string(project_path.join("app-c3/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("link-arg=-Tlinkall.x"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("ch32")
+        .arg("--preset")
+        .arg("ch32v307")
+        .arg("--panic")
+        .arg("probe")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cortex-M specific"));
+}
+
+/// Test Xtensa platforms are routed through the espup-installed esp toolchain
+#[test]
+fn test_xtensa_requires_esp_toolchain() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    let output = cmd
+        .current_dir(&project_path)
+        .arg("add-platform")
+        .arg("esp32")
+        .arg("--target")
+        .arg("xtensa-esp32-none-elf")
+        .arg("--hal")
+        .arg("nb")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    if !String::from_utf8_lossy(&output.stdout).contains("espup install") {
+        // The esp toolchain is already installed on this machine
+        return;
+    }
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--target")
+        .arg("esp32")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs the esp Rust toolchain"))
+        .stderr(predicate::str::contains("espup"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--target")
+        .arg("esp32")
+        .arg("--cross")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cross has no Xtensa images"));
+}
+
+/// Test AVR platforms get avr-hal scaffolding, build-std and a ravedude runner
+#[test]
+fn test_add_platform_avr() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("arduino")
+        .arg("--target")
+        .arg("avr-unknown-gnu-atmega328")
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("runner = \"ravedude uno -cb 57600\""));
+    assert!(glue.contains("hal_crate = \"arduino-hal\""));
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-arduino/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("build-std = [\"core\"]"));
+    assert!(cargo_config.contains("panic = \"abort\""));
+    assert!(!cargo_config.contains("link.x"));
+
+    let toolchain =
+        fs::read_to_string(project_path.join("app-arduino/rust-toolchain.toml")).unwrap();
+    assert!(toolchain.contains("channel = \"nightly\""));
+    assert!(!project_path.join("app-arduino/memory.x").exists());
+
+    let app_manifest = fs::read_to_string(project_path.join("app-arduino/Cargo.toml")).unwrap();
+    assert!(app_manifest.contains(
+        "arduino-hal = { git = \"https://github.com/rahix/avr-hal\", features = [\"arduino-uno\"] }"
+    ));
+    assert!(!app_manifest.contains("cortex-m-rt"));
+    let app_main = fs::read_to_string(project_path.join("app-arduino/src/main.rs")).unwrap();
+    assert!(app_main.contains("#[arduino_hal::entry]"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("nano")
+        .arg("--target")
+        .arg("avr-unknown-gnu-atmega328")
+        .arg("--alloc")
+        .arg("embedded-alloc")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not supported on AVR"));
+
+    // --chip picks the board, by its name or its MCU
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "nano",
+            "--target",
+            "avr-unknown-gnu-atmega328",
+        ])
+        .args(["--chip", "nano"])
+        .assert()
+        .success();
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("runner = \"ravedude nano -cb 57600\""));
+    assert!(glue.contains("hal_features = [\"arduino-nano\"]"));
+    let app_manifest = fs::read_to_string(project_path.join("app-nano/Cargo.toml")).unwrap();
+    assert!(app_manifest.contains("features = [\"arduino-nano\"]"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "every",
+            "--target",
+            "avr-unknown-gnu-atmega328",
+        ])
+        .args(["--chip", "atmega4809"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "No avr-hal board for --chip 'atmega4809'",
+        ));
+}
+
+/// Test the startup runtime is recorded per platform and can be overridden
+#[test]
+fn test_add_platform_runtime() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    // An ESP HAL on a RISC-V triple would infer esp-riscv-rt; force plain riscv-rt instead
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("custom")
+        .arg("--target")
+        .arg("riscv32imc-unknown-none-elf")
+        .arg("--hal")
+        .arg("esp-hal")
+        .arg("--runtime")
+        .arg("riscv-rt")
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("runtime = \"cortex-m-rt\""));
+    assert!(glue.contains("runtime = \"riscv-rt\""));
+
+    let app_manifest = fs::read_to_string(project_path.join("app-custom/Cargo.toml")).unwrap();
+    assert!(app_manifest.contains("riscv-rt"));
+    assert!(!app_manifest.contains("esp-hal"));
+    assert!(project_path.join("app-custom/memory.x").exists());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("list-platforms")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Runtime: cortex-m-rt"))
+        .stdout(predicate::str::contains("Runtime: riscv-rt"));
+}
+
+/// Test platform builds are copied to artifacts/ with a manifest
+#[test]
+fn test_build_writes_artifact_manifest() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--target")
+        .arg("desk")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "artifacts/desk/debug/manifest.json",
+        ));
+
+    let manifest: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(project_path.join("artifacts/desk/debug/manifest.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(manifest["platform"], "desk");
+    assert_eq!(manifest["profile"], "debug");
+    let artifact = &manifest["artifacts"][0];
+    assert_eq!(artifact["name"], "desk");
+    assert_eq!(artifact["sha256"].as_str().unwrap().len(), 64);
+
+    // Sizes come from the loadable segments, not the file with its symbols and debug info
+    let binary = fs::read(project_path.join(artifact["path"].as_str().unwrap())).unwrap();
+    let flash = artifact["flash"].as_u64().unwrap();
+    assert!(flash > 0 && flash < binary.len() as u64);
+    assert!(artifact["ram"].as_u64().unwrap() > 0);
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["size", "--target", "desk"])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::is_match(r"desk\s+flash\s+[0-9.]+ KiB\s+RAM\s+[0-9.]+ KiB").unwrap(),
+        );
+}
+
+/// Test build --timings aggregates cargo's timing messages across platforms
+#[cfg(unix)]
+#[test]
+fn test_build_timings_report() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--timings")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("across 1 build(s) (host)"));
+
+    // A stable cargo has no --timings=json, so the host build only records its wall time
+    let timings_dir = project_path.join("artifacts/timings");
+    let host: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(timings_dir.join("host.json")).unwrap()).unwrap();
+    assert!(host["wall_seconds"].as_f64().unwrap() > 0.0);
+    assert!(host["units"].as_array().unwrap().is_empty());
+
+    // Stand-in for a nightly cargo with --timings=json: runs the real cargo with plain --timings
+    // and adds the timing-info messages it would print
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let fake_cargo = bin_dir.join("cargo");
+    fs::write(
+        &fake_cargo,
+        format!(
+            r#"#!/bin/sh
+case "$*" in
+  -V) echo 'cargo 1.80.0-nightly (0000000 2024-05-01)'; exit 0 ;;
+  *--timings=json*--help) exit 0 ;;
+esac
+for arg do
+  shift
+  case "$arg" in
+    -Zunstable-options) ;;
+    --timings=html,json) set -- "$@" --timings ;;
+    *) set -- "$@" "$arg" ;;
+  esac
+done
+{} "$@" || exit $?
+echo '{{"reason":"timing-info","package_id":"path+file:///w/core-lib#0.1.0","target":{{"kind":["lib"],"name":"core_lib"}},"mode":"build","duration":1.25,"rmeta_time":0.5}}'
+echo '{{"reason":"timing-info","package_id":"path+file:///w/app-desk#app-desk@0.1.0","target":{{"kind":["bin"],"name":"app-desk"}},"mode":"build","duration":0.75,"rmeta_time":null}}'
+"#,
+            env!("CARGO")
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_cargo, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .arg("build")
+        .arg("--target")
+        .arg("desk")
+        .arg("--timings")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("across 2 build(s) (desk, host)"))
+        .stdout(predicate::str::is_match(r"core-lib\s+1\.25s").unwrap());
+
+    let desk: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(timings_dir.join("desk.json")).unwrap()).unwrap();
+    assert_eq!(desk["target"], "x86_64-unknown-linux-gnu");
+    let units = desk["units"].as_array().unwrap();
+    assert_eq!(units.len(), 2);
+    assert_eq!(units[0]["version"], "0.1.0");
+    assert_eq!(units[0]["target"], "lib");
+    assert_eq!(units[1]["name"], "app-desk");
+    assert_eq!(units[1]["duration"], 0.75);
+
+    let report = fs::read_to_string(timings_dir.join("report.html")).unwrap();
+    assert!(report.contains("<a href=\"desk.html\">desk</a>"));
+    assert!(report.contains("<td>core-lib</td>"));
+    assert!(timings_dir.join("host.html").exists());
+}
+
+/// Test sccache from glue.toml wraps rustc for host builds
+#[cfg(unix)]
+#[test]
+fn test_build_with_sccache() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!("{}\n[build_config]\nsccache = true\n", glue),
+    )
+    .unwrap();
+
+    // Stand-in for sccache that logs each wrapped compiler call and then runs it
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("sccache.log");
+    let fake_sccache = bin_dir.join("sccache");
+    fs::write(
+        &fake_sccache,
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = \"--show-stats\" ]; then echo 'Compile requests 0'; exit 0; fi\necho \"$@\" >> {}\nexec \"$@\"\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_sccache, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Using sccache as the rustc wrapper",
+        ));
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("--crate-name core_lib"));
+}
+
+/// Test cross image overrides are written to Cross.toml by glue sync
+#[test]
+fn test_cross_toml_from_glue() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--cross-image")
+        .arg("ghcr.io/example/arm-sdk:1.0")
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("cross_image = \"ghcr.io/example/arm-sdk:1.0\""));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote Cross.toml"));
+
+    let cross = fs::read_to_string(project_path.join("Cross.toml")).unwrap();
+    assert!(cross.starts_with("# Generated by multi-target-rs"));
+    assert!(cross.contains("[target.thumbv7em-none-eabihf]"));
+    assert!(cross.contains("image = \"ghcr.io/example/arm-sdk:1.0\""));
+}
+
+/// Test devices declared in glue.toml are listed and select a probe when running
+#[cfg(unix)]
+#[test]
+fn test_devices_list_and_run() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .arg("--runner")
+        .arg("probe-rs run --chip STM32F411RETx")
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\nchip = \"STM32F411CEUx\"\nlocation = \"rack A\"\ntags = [\"ci\"]\n\n[[devices]]\nname = \"bench-2\"\nplatform = \"desk\"\nprobe = \"0483:374b:0002\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("devices")
+        .arg("list")
+        .arg("--tag")
+        .arg("ci")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "bench-1 (desk, x86_64-unknown-linux-gnu)",
+        ))
+        .stdout(predicate::str::contains("Location: rack A"))
+        .stdout(predicate::str::contains("bench-2").not());
+
+    // Stand-in for probe-rs that records how it was invoked
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("probe-rs.log");
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .arg("run")
+        .arg("--device")
+        .arg("bench-1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Device 'bench-1' at rack A"));
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.starts_with("run --chip STM32F411CEUx --probe 0483:374b:0001 "));
+
+    // flash and monitor take the device's probe and chip too, and the image from the manifest
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk"])
+        .assert()
+        .success();
+    for command in ["flash", "monitor"] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .env("PATH", &path)
+            .args([command, "--device", "bench-1"])
+            .assert()
+            .success();
+    }
+    let calls = fs::read_to_string(&log).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert!(calls[1].starts_with("download --chip STM32F411CEUx --probe 0483:374b:0001 "));
+    assert!(calls[1].ends_with("/artifacts/desk/debug/desk"));
+    assert!(calls[2].starts_with("attach --chip STM32F411CEUx --probe 0483:374b:0001 "));
+    assert!(calls[2].ends_with("/artifacts/desk/debug/desk"));
+}
+
+/// Test deploy flashes every matching device and fails when one of them does
+#[cfg(unix)]
+#[test]
+fn test_deploy_to_devices() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .arg("--runner")
+        .arg("probe-rs run --chip STM32F411RETx")
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\ntags = [\"rack-1\"]\n\n[[devices]]\nname = \"bench-2\"\nplatform = \"desk\"\nprobe = \"0483:374b:0002\"\ntags = [\"rack-1\"]\n\n[[devices]]\nname = \"bench-3\"\nplatform = \"desk\"\nprobe = \"0483:374b:0003\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--target")
+        .arg("desk")
+        .assert()
+        .success();
+
+    // Stand-in for probe-rs where the second probe is missing
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("probe-rs.log");
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> {}\ncase \"$*\" in *0002*) echo 'Error: probe not found' >&2; exit 1;; esac\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .arg("deploy")
+        .arg("--platform")
+        .arg("desk")
+        .arg("--tag")
+        .arg("rack-1")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("✅ bench-1"))
+        .stderr(predicate::str::contains(
+            "❌ bench-2: Error: probe not found",
+        ))
+        .stdout(predicate::str::contains("bench-3").not())
+        .stderr(predicate::str::contains("1 of 2 device(s) failed to flash"));
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("download --chip STM32F411RETx --probe 0483:374b:0001 "));
+    assert!(calls.contains("artifacts/desk/debug/desk"));
+
+    // `run`-only probe-rs options stay behind, and ravedude flashes without its console
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        glue.replace(
+            "runner = \"probe-rs run --chip STM32F411RETx\"",
+            "runner = \"probe-rs run --chip STM32F411RETx --catch-hardfault --connect-under-reset\"",
+        ),
+    )
+    .unwrap();
+    fs::remove_file(&log).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["flash", "--device", "bench-1"])
+        .assert()
+        .success();
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.starts_with(
+        "download --chip STM32F411RETx --probe 0483:374b:0001 --connect-under-reset /"
+    ));
+
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        glue.replace(
+            "probe-rs run --chip STM32F411RETx --catch-hardfault --connect-under-reset",
+            "ravedude uno -cb 57600",
+        ),
+    )
+    .unwrap();
+    fs::write(
+        bin_dir.join("ravedude"),
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(bin_dir.join("ravedude"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::remove_file(&log).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["flash", "--target", "desk"])
+        .assert()
+        .success();
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.starts_with("uno -b 57600 /"));
+}
+
+/// Test host test results are written as a JUnit report, including failures
+#[test]
+fn test_junit_report() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let test_path = project_path.join("tests/integration_test.rs");
+    let tests = fs::read_to_string(&test_path).unwrap();
+    fs::write(
+        &test_path,
+        format!(
+            "{}\n#[test]\nfn deliberately_fails() {{\n    assert_eq!(1, 2, \"boom <here>\");\n}}\n",
+            tests
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .arg("--report")
+        .arg("junit:target/junit.xml")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Wrote JUnit report for 3 test(s) in 2 suite(s)",
+        ));
+
+    // One <testsuite> per test binary cargo ran, up to the one that failed
+    let junit = fs::read_to_string(project_path.join("target/junit.xml")).unwrap();
+    assert!(junit.contains(
+        "<testsuite name=\"host::core_lib (unittests src/lib.rs)\" tests=\"0\" failures=\"0\" skipped=\"0\">"
+    ));
+    assert!(junit.contains(
+        "<testsuite name=\"host::integration (integration_test.rs)\" tests=\"3\" failures=\"1\" skipped=\"0\">"
+    ));
+    assert!(junit
+        .contains("<testcase classname=\"host::integration\" name=\"test_temperature_sensor\"/>"));
+    assert!(junit.contains("name=\"deliberately_fails\">"));
+    assert!(junit.contains("boom &lt;here&gt;"));
+}
+
+/// Test arguments after `--` reach cargo and the test binaries
+#[test]
+fn test_passthrough_args() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    // A bare filter goes to the test harness
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .arg("--")
+        .arg("--nocapture")
+        .arg("temp")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "test test_temperature_sensor ... ok",
+        ))
+        .stdout(predicate::str::contains("test_application_led_toggle").not());
+
+    // Before a second `--`, arguments go to cargo itself
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .arg("--")
+        .arg("--test")
+        .arg("integration")
+        .arg("--")
+        .arg("led")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "test test_application_led_toggle ... ok",
+        ))
+        .stdout(predicate::str::contains("test_temperature_sensor").not());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--")
+        .arg("--no-such-flag")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--no-such-flag"));
+}
+
+/// Test doc --all documents each platform with its triple and links them from an index
+#[test]
+fn test_doc_all_index() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("doc")
+        .arg("--all")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Documenting platform: desk"));
+
+    let target_dir = project_path.join("target");
+    assert!(target_dir
+        .join("x86_64-unknown-linux-gnu/doc/hal_desk/index.html")
+        .exists());
+    let index = fs::read_to_string(target_dir.join("doc-index.html")).unwrap();
+    assert!(index.contains("<a href=\"doc/core_lib/index.html\">core_lib</a>"));
+    assert!(
+        index.contains("<a href=\"x86_64-unknown-linux-gnu/doc/hal_desk/index.html\">hal_desk</a>")
+    );
+}
+
+/// Test report matrix lists every platform with its latest build size
+#[test]
+fn test_report_matrix() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--hal")
+        .arg("stm32f4xx-hal")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--target")
+        .arg("desk")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("report")
+        .arg("matrix")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "| Platform | Target | Chip | Runtime | HAL |",
+        ))
+        .stdout(predicate::str::contains(
+            "| stm32 | thumbv7em-none-eabihf |  | cortex-m-rt | stm32f4xx-hal |",
+        ))
+        .stdout(predicate::str::contains("| not built |"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("report")
+        .arg("matrix")
+        .arg("--format")
+        .arg("html")
+        .arg("--output")
+        .arg("matrix.html")
+        .assert()
+        .success();
+
+    let html = fs::read_to_string(project_path.join("matrix.html")).unwrap();
+    assert!(html.contains("<td>desk</td><td>x86_64-unknown-linux-gnu</td>"));
+    assert!(html.contains(" KiB RAM (debug)</td>"));
+}
+
+/// Test glue.toml round-trips through JSON and YAML export and import
+#[test]
+fn test_glue_export_import() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("export")
+        .arg("--format")
+        .arg("yaml")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("- name: stm32"))
+        .stdout(predicate::str::contains("target: thumbv7em-none-eabihf"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("export")
+        .arg("--output")
+        .arg("glue.json")
+        .assert()
+        .success();
+
+    // Another tool renames the platform and hands the file back
+    let json = fs::read_to_string(project_path.join("glue.json")).unwrap();
+    assert!(json.contains("\"name\": \"stm32\""));
+    fs::write(
+        project_path.join("glue.json"),
+        json.replace("\"name\": \"stm32\"", "\"name\": \"nucleo\""),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("import")
+        .arg("glue.json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Imported 1 platform(s)"))
+        .stdout(predicate::str::contains("app-nucleo does not exist yet"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("name = \"nucleo\""));
+    assert!(glue.contains("target = \"thumbv7em-none-eabihf\""));
+}
+
+/// Test chip and probe settings are imported from a cargo-embed Embed.toml
+#[test]
+fn test_glue_import_embed() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    fs::write(
+        project_path.join("Embed.toml"),
+        "[default.general]\nchip = \"STM32F411RETx\"\n\n[default.probe]\nprotocol = \"Swd\"\nspeed = 4000\n\n[default.rtt]\nenabled = true\n\n[bench.probe]\nprobe_selector = \"0483:374b:0671FF\"\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("import-embed")
+        .arg("Embed.toml")
+        .arg("--platform")
+        .arg("stm32")
+        .arg("--profile")
+        .arg("bench")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[rtt] settings are not imported"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("chip = \"STM32F411RETx\""));
+    assert!(glue.contains(
+        "runner = \"probe-rs run --chip STM32F411RETx --protocol swd --speed 4000 --probe 0483:374b:0671FF\""
+    ));
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-stm32/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("--probe 0483:374b:0671FF"));
+}
+
+/// Test PlatformIO environments are adopted as platforms via the board catalog
+#[test]
+fn test_adopt_platformio() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    fs::write(
+        project_path.join("platformio.ini"),
+        "; PlatformIO Project Configuration File\n[env]\nframework = arduino\n\n[env:nucleo_f411re]\nplatform = ststm32\nboard = nucleo_f411re\nbuild_flags =\n    -DLED_PIN=13\n\n[env:uno]\nboard = uno\n\n[env:mystery]\nboard = teensy41\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("adopt")
+        .arg("--from")
+        .arg("platformio")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "env:nucleo_f411re (board nucleo_f411re) → platform 'nucleo-f411re'",
+        ))
+        .stderr(predicate::str::contains(
+            "Skipping env 'mystery': board 'teensy41' is not in the catalog",
+        ))
+        .stdout(predicate::str::contains(
+            "Adopted 2 PlatformIO environment(s)",
+        ));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("target = \"thumbv7em-none-eabihf\""));
+    assert!(glue.contains("chip = \"STM32F411RETx\""));
+    assert!(glue.contains("runner = \"probe-rs run --chip STM32F411RETx\""));
+    assert!(glue.contains("target = \"avr-unknown-gnu-atmega328\""));
+    assert!(project_path.join("hal-nucleo-f411re/Cargo.toml").exists());
+    assert!(project_path.join("app-uno/Cargo.toml").exists());
+}
+
+/// Test per-profile defmt log filters reach the cargo config and build manifests
+#[test]
+fn test_defmt_log_per_profile() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("desk")
+        .arg("--target")
+        .arg("x86_64-unknown-linux-gnu")
+        .arg("--hal")
+        .arg("nb")
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        glue.replace(
+            "name = \"desk\"\n",
+            "name = \"desk\"\ndefmt_log = { dev = \"debug\", release = \"core_lib=trace,info\" }\n",
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success();
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-desk/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("[env]\nDEFMT_LOG = \"debug\""));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .arg("--target")
+        .arg("desk")
+        .arg("--release")
+        .assert()
+        .success();
+
+    let manifest =
+        fs::read_to_string(project_path.join("artifacts/desk/release/manifest.json")).unwrap();
+    assert!(manifest.contains("\"defmt_log\": \"core_lib=trace,info\""));
+}
+
+/// Test RTT channels from glue.toml generate the app's rtt module and dependency
+#[test]
+fn test_rtt_channels() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        glue.replace(
+            "name = \"stm32\"\n",
+            "name = \"stm32\"\nrtt = { up = [{ name = \"Terminal\", size = 1024 }, { name = \"defmt\", size = 2048, mode = \"block-if-full\" }], down = [{ name = \"Terminal\", size = 16 }] }\n",
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote app-stm32/src/rtt.rs"));
+
+    let rtt = fs::read_to_string(project_path.join("app-stm32/src/rtt.rs")).unwrap();
+    assert!(rtt.contains("0: { size: 1024, mode: ChannelMode::NoBlockSkip, name: \"Terminal\" }"));
+    assert!(rtt.contains("1: { size: 2048, mode: ChannelMode::BlockIfFull, name: \"defmt\" }"));
+    assert!(rtt.contains("rtt_target::set_print_channel(channels.up.0);"));
+    assert!(rtt.contains("rtt_target::set_defmt_channel(channels.up.1);"));
+    assert!(rtt.contains("down_terminal: channels.down.0,"));
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-stm32/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("\"link-arg=-Tdefmt.x\""));
+
+    // Syncing again keeps a single dependency line
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success();
+    let manifest = fs::read_to_string(project_path.join("app-stm32/Cargo.toml")).unwrap();
+    assert_eq!(
+        manifest
+            .matches("rtt-target = { version = \"0.6\", features = [\"defmt\"] }")
+            .count(),
+        1
+    );
+    assert_eq!(manifest.matches("defmt = { workspace = true }").count(), 1);
+}
+
+/// Test --io semihosting wires panic-semihosting, hprintln! and a QEMU runner
+#[test]
+fn test_io_semihosting() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("qemu")
+        .arg("--target")
+        .arg("thumbv7m-none-eabi")
+        .arg("--io")
+        .arg("semihosting")
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("io = \"semihosting\""));
+    assert!(glue.contains("panic = \"semihosting\""));
+
+    let cargo_config =
+        fs::read_to_string(project_path.join("app-qemu/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains(
+        "qemu-system-arm -cpu cortex-m3 -machine lm3s6965evb -nographic -semihosting-config enable=on,target=native -kernel"
+    ));
+
+    let manifest = fs::read_to_string(project_path.join("app-qemu/Cargo.toml")).unwrap();
+    assert!(manifest.contains("cortex-m-semihosting = \"0.5\""));
+    assert!(manifest.contains("panic-semihosting = \"0.6\""));
+
+    let main = fs::read_to_string(project_path.join("app-qemu/src/main.rs")).unwrap();
+    assert!(main.contains("hprintln!(\"app-qemu started\");"));
+    assert!(main.contains("debug::exit(debug::EXIT_SUCCESS);"));
+
+    let memory = fs::read_to_string(project_path.join("app-qemu/memory.x")).unwrap();
+    assert!(memory.contains("FLASH : ORIGIN = 0x00000000"));
+
+    // Semihosting is Cortex-M only
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("rv")
+        .arg("--target")
+        .arg("riscv32imac-unknown-none-elf")
+        .arg("--io")
+        .arg("semihosting")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs a Cortex-M target"));
+}
+
+/// Test `test --miri` installs Miri when missing and skips platform and miri_exclude crates
+#[cfg(unix)]
+#[test]
+fn test_miri_host_tests() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!("{}\n[build_config]\nmiri_exclude = [\"ffi-shim\"]\n", glue),
+    )
+    .unwrap();
+
+    // Stand-ins for rustup (Miri not yet installed) and cargo
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("tools.log");
+    for tool in ["rustup", "cargo"] {
+        let fake = bin_dir.join(tool);
+        fs::write(
+            &fake,
+            format!("#!/bin/sh\necho \"{} $@\" >> {}\n", tool, log.display()),
+        )
+        .unwrap();
+        fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .arg("test")
+        .arg("--miri")
+        .arg("--")
+        .arg("temp_sensor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Installing Miri"))
+        .stdout(predicate::str::contains("Skipping miri_exclude: ffi-shim"));
+
+    let log = fs::read_to_string(&log).unwrap();
+    assert!(log.contains("rustup toolchain install nightly --profile minimal --component miri"));
+    assert!(log.contains(
+        "cargo +nightly miri test --workspace --exclude app-* --exclude hal-* --exclude boot-* --exclude bsp-* --exclude tests-target-* --exclude bench-target-* --exclude latency-target-* --exclude ffi-shim -- temp_sensor"
+    ));
+}
+
+/// Test --proptest scaffolds property tests for core-lib and drivers that pass
+#[test]
+fn test_proptest_scaffolding() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .arg("--proptest")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let properties = fs::read_to_string(project_path.join("tests/properties_test.rs")).unwrap();
+    assert!(properties.contains("fn application_matches_model"));
+    let readme = fs::read_to_string(project_path.join("README.md")).unwrap();
+    assert!(readme.contains("## Property Tests"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("new-driver")
+        .arg("bme280")
+        .arg("--proptest")
+        .assert()
+        .success();
+    assert!(project_path
+        .join("drivers/bme280/tests/properties.rs")
+        .exists());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Running native unit tests"));
+}
+
+/// Test new-bench creates a criterion benchmark that bench runs
+#[test]
+fn test_new_bench_and_bench() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bench")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("new-bench <name>"));
+
+    for name in ["crc", "moving-average"] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("new-bench")
+            .arg(name)
+            .assert()
+            .success();
+    }
+
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert_eq!(workspace.matches("\"benches\",").count(), 1);
+    let manifest = fs::read_to_string(project_path.join("benches/Cargo.toml")).unwrap();
+    assert!(manifest.contains("criterion = \"0.5\""));
+    assert!(manifest
+        .contains("name = \"moving-average\"\npath = \"moving_average.rs\"\nharness = false"));
+
+    // --test runs each benchmark once instead of measuring it
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bench")
+        .arg("crc")
+        .arg("--")
+        .arg("--test")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Testing crc"));
+}
+
+/// Test --examples selects the starter code and host tests generated at init
+#[test]
+fn test_init_examples() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("console")
+        .arg("--examples")
+        .arg("uart-console")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("console");
+    let lib = fs::read_to_string(project_path.join("core-lib/src/lib.rs")).unwrap();
+    assert!(lib.contains("pub struct Console<S>"));
+    assert!(!lib.contains("TemperatureSensor"));
+    assert!(!lib.contains("LedController"));
+    let core_manifest = fs::read_to_string(project_path.join("core-lib/Cargo.toml")).unwrap();
+    assert!(core_manifest.contains("embedded-hal-nb = { workspace = true }"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .success();
+
+    // Platforms and the simulator follow the selection
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+    let hal = fs::read_to_string(project_path.join("hal-stm32/src/lib.rs")).unwrap();
+    assert!(!hal.contains("LedController"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("sim")
+        .arg("--simulator")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("initialized without temp-sensor"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("empty")
+        .arg("--examples")
+        .arg("none")
+        .assert()
+        .success();
+    let lib = fs::read_to_string(temp.path().join("empty/core-lib/src/lib.rs")).unwrap();
+    assert!(!lib.contains("pub "));
+    let glue = fs::read_to_string(temp.path().join("empty/glue.toml")).unwrap();
+    assert!(glue.contains("examples = []"));
+
+    // Repeated examples are generated once, in the order first given
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args([
+            "init",
+            "repeated",
+            "--no-git",
+            "--examples",
+            "led,temp-sensor,led",
+        ])
+        .assert()
+        .success();
+    let glue = fs::read_to_string(temp.path().join("repeated/glue.toml")).unwrap();
+    assert!(glue.contains("examples = [\n    \"led\",\n    \"temp-sensor\",\n]"));
+}
+
+/// Test init --bare leaves out the examples and README
+#[test]
+fn test_init_bare() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .arg("--bare")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    assert!(!project_path.join("README.md").exists());
+    assert!(project_path.join("glue.toml").exists());
+    let lib = fs::read_to_string(project_path.join("core-lib/src/lib.rs")).unwrap();
+    assert!(!lib.contains("pub "));
+    let tests = fs::read_to_string(project_path.join("tests/integration_test.rs")).unwrap();
+    assert!(!tests.contains("#[test]"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("other")
+        .arg("--bare")
+        .arg("--examples")
+        .arg("led")
+        .assert()
+        .failure();
+}
+
+/// Test init --lib-only and how list-platforms, validate and add-platform treat it
+#[test]
+fn test_init_lib_only() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .arg("--lib-only")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("lib_only = true"));
+    let manifest = fs::read_to_string(project_path.join("core-lib/Cargo.toml")).unwrap();
+    assert!(manifest.contains("categories = [\"embedded\", \"no-std\"]"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("list-platforms")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Library-only project"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("validate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Library-only layout: core-lib and tests; 0 example consumer(s)",
+        ));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("example consumer"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("list-platforms")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Example consumers:"))
+        .stdout(predicate::str::contains("stm32 (thumbv7em-none-eabihf)"));
+}
+
+/// Test init --core with an existing crate outside the project and one from crates.io
+#[test]
+fn test_init_core() {
+    let temp = TempDir::new().unwrap();
+    let core_path = temp.path().join("sensor-core");
+    fs::create_dir_all(core_path.join("src")).unwrap();
+    fs::write(
+        core_path.join("Cargo.toml"),
+        "[package]\nname = \"sensor-core\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[features]\nstd = []\n",
+    )
+    .unwrap();
+    fs::write(
+        core_path.join("src/lib.rs"),
+        "#![cfg_attr(not(feature = \"std\"), no_std)]\npub fn answer() -> u8 {\n    42\n}\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .arg("--core")
+        .arg("sensor-core")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Using sensor-core"));
+
+    let project_path = temp.path().join("testproj");
+    assert!(!project_path.join("core-lib").exists());
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(!workspace.contains("\"core-lib\""));
+    let tests = fs::read_to_string(project_path.join("tests/Cargo.toml")).unwrap();
+    assert!(tests.contains(
+        "core-lib = { package = \"sensor-core\", path = \"../../sensor-core\", features = [\"std\"] }"
+    ));
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("[core]"));
+    assert!(glue.contains("package = \"sensor-core\""));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("registryproj")
+        .arg("--core")
+        .arg("sensor-core-registry@0.2")
+        .assert()
+        .success();
+    let tests = fs::read_to_string(temp.path().join("registryproj/tests/Cargo.toml")).unwrap();
+    assert!(tests.contains("core-lib = { package = \"sensor-core-registry\", version = \"0.2\" }"));
+}
+
+/// Test the [lockfile] policy: a missing or drifted Cargo.lock stops build and test
+#[test]
+fn test_lockfile_policy() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    glue.push_str("\n[lockfile]\ncommit = true\nmode = \"locked\"\n");
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cargo.lock is missing"));
+
+    std::process::Command::new("cargo")
+        .arg("generate-lockfile")
+        .current_dir(&project_path)
+        .status()
+        .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Building with --locked"));
+
+    let mut manifest = fs::read_to_string(project_path.join("tests/Cargo.toml")).unwrap();
+    manifest.push_str("\n[dev-dependencies]\nproptest = \"1\"\n");
+    fs::write(project_path.join("tests/Cargo.toml"), manifest).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cargo.lock has drifted"));
+}
+
+/// Test that platform names become `platform` cfg values registered with check-cfg
+#[test]
+fn test_platform_cfg_aliases() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    for (name, target) in [
+        ("stm32", "thumbv7em-none-eabihf"),
+        ("desktop", "x86_64-unknown-linux-gnu"),
+    ] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("add-platform")
+            .arg(name)
+            .arg("--target")
+            .arg(target)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Registered platform cfgs"));
+    }
+
+    let check_cfg = "'cfg(platform, values(\"stm32\", \"desktop\"))'";
+    let root = fs::read_to_string(project_path.join(".cargo/config.toml")).unwrap();
+    assert!(root.contains(check_cfg));
+    assert!(root.contains("target-dir = \"target\""));
+    // The first app is refreshed when the second platform is added
+    let app = fs::read_to_string(project_path.join("app-stm32/.cargo/config.toml")).unwrap();
+    assert!(app.contains("'platform=\"stm32\"'"));
+    assert!(app.contains(check_cfg));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("remove")
+        .arg("desktop")
+        .assert()
+        .success();
+    let root = fs::read_to_string(project_path.join(".cargo/config.toml")).unwrap();
+    assert!(root.contains("'cfg(platform, values(\"stm32\"))'"));
+}
+
+/// Test that apps get a build.rs for memory.x and that glue sync regenerates memory.x
+#[test]
+fn test_memory_x_build_script() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let app_path = project_path.join("app-stm32");
+    let build = fs::read_to_string(app_path.join("build.rs")).unwrap();
+    assert!(build.contains("cargo:rustc-link-search="));
+    assert!(build.contains("include_bytes!(\"memory.x\")"));
+
+    // A changed memory layout in glue.toml reaches memory.x, and a lost build.rs comes back
+    fs::remove_file(app_path.join("build.rs")).unwrap();
+    let mut glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    glue.push_str(
+        "\n[platforms.memory]\nflash_origin = 134217728\nflash_length = 524288\nram_origin = 536870912\nram_length = 131072\n",
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Regenerated app-stm32/memory.x"))
+        .stdout(predicate::str::contains("Added app-stm32/build.rs"));
+    let memory = fs::read_to_string(app_path.join("memory.x")).unwrap();
+    assert!(memory.contains("512K"));
+    assert!(app_path.join("build.rs").exists());
+}
+
+/// Test per-platform rustflags from glue.toml in the generated cargo config
+#[test]
+fn test_platform_rustflags() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    let mut glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    glue.push_str(
+        "rustflags = [\"-C\", \"target-cpu=cortex-m4\", \"--cfg\", \"portable_atomic_unsafe_assume_single_core\", \"-C\", \"link-arg=-Tlink.x\"]\n",
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("sync")
+        .assert()
+        .success();
+    let config = fs::read_to_string(project_path.join("app-stm32/.cargo/config.toml")).unwrap();
+    assert!(config.contains("\"target-cpu=cortex-m4\""));
+    assert!(config.contains("\"portable_atomic_unsafe_assume_single_core\""));
+    // The link script pair is generated already and is not repeated
+    assert_eq!(config.matches("link-arg=-Tlink.x").count(), 1);
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("list-platforms")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "RUSTFLAGS: -C target-cpu=cortex-m4 --cfg portable_atomic_unsafe_assume_single_core",
+        ));
+}
+
+/// Test that test --on-target scaffolds the defmt-test crate and runs it through probe-rs
+#[cfg(unix)]
+#[test]
+fn test_on_target_defmt_test() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--chip")
+        .arg("STM32F411RETx")
+        .assert()
+        .success();
+
+    // Stand-in for cargo, since defmt-test needs a probe to run
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("cargo.log");
+    let fake = bin_dir.join("cargo");
+    fs::write(
+        &fake,
+        format!("#!/bin/sh\necho \"cargo $@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .arg("test")
+        .arg("--target")
+        .arg("stm32")
+        .arg("--on-target")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created tests-target-stm32"))
+        .stdout(predicate::str::contains(
+            "Using runner: probe-rs run --chip STM32F411RETx",
+        ));
+
+    let crate_path = project_path.join("tests-target-stm32");
+    let manifest = fs::read_to_string(crate_path.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("defmt-test = \"0.3\""));
+    assert!(manifest.contains("harness = false"));
+    let unit = fs::read_to_string(crate_path.join("tests/unit.rs")).unwrap();
+    assert!(unit.contains("#[defmt_test::tests]"));
+    assert!(unit.contains("use tests_target_stm32 as _;"));
+    let config = fs::read_to_string(crate_path.join(".cargo/config.toml")).unwrap();
+    assert!(config.contains("link-arg=-Tdefmt.x"));
+    assert!(crate_path.join("memory.x").exists());
+    assert!(crate_path.join("build.rs").exists());
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"tests-target-stm32\""));
+
+    let log = fs::read_to_string(&log).unwrap();
+    assert!(log.contains("test --target thumbv7em-none-eabihf -p tests-target-stm32"));
+}
+
+/// Test chips search against the probe-rs registry and the built-in fallback
+#[cfg(unix)]
+#[test]
+fn test_chips_search() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("PATH", "/nonexistent")
+        .arg("chips")
+        .arg("search")
+        .arg("nrf528")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("built-in list"))
+        .stdout(predicate::str::contains("nRF52840_xxAA"));
+
+    // Stand-in for probe-rs answering `chip list` and `chip info`
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let fake = bin_dir.join("probe-rs");
+    fs::write(
+        &fake,
+        r#"#!/bin/sh
+if [ "$2" = "list" ]; then
+    printf 'Available chips:\nSTM32F4 Series\n    Variants:\n        - STM32F411RETx\n        - STM32F446RETx\nnRF52840\n    Variants:\n        - nRF52840_xxAA\n'
+else
+    printf '%s\nCores (1):\n    - main (Armv7em)\nNVM: 0x08000000..0x08080000 (512 KiB)\nRAM: 0x20000000..0x20020000 (128 KiB)\n' "$3"
+fi
+"#,
+    )
+    .unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("PATH", &bin_dir)
+        .arg("chips")
+        .arg("search")
+        .arg("stm32f4")
+        .arg("--limit")
+        .arg("1")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("probe-rs target registry"))
+        .stdout(
+            predicate::str::is_match(
+                r"STM32F411RETx\s+STM32F4 Series\s+Armv7em\s+flash\s+512 KiB\s+RAM\s+128 KiB",
+            )
+            .unwrap(),
+        )
+        .stdout(predicate::str::contains("1 more"))
+        .stdout(predicate::str::contains("chip = \"STM32F411RETx\""));
+}
+
+/// Test add-platform --chip without --target infers the triple from the chip's core
+#[test]
+fn test_target_from_chip() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    for (name, chip, target) in [
+        ("nrf", "nRF52840_xxAA", "thumbv7em-none-eabihf"),
+        ("pico", "RP2040", "thumbv6m-none-eabi"),
+        ("bluepill", "STM32F103C8", "thumbv7m-none-eabi"),
+    ] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .env("PATH", "/nonexistent")
+            .arg("add-platform")
+            .arg(name)
+            .arg("--chip")
+            .arg(chip)
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!("using target {}", target)));
+    }
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("target = \"thumbv6m-none-eabi\""));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", "/nonexistent")
+        .arg("add-platform")
+        .arg("mystery")
+        .arg("--chip")
+        .arg("XYZ123")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Can't tell the target for chip 'XYZ123'",
+        ));
+}
+
+/// Test that detected HAL peripherals are listed and checked against board resources
+#[test]
+fn test_hal_peripherals() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    // What `glue init` records after inspecting the HAL source
+    let mut glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    glue.push_str(
+        r#"
+[platforms.hal_info]
+source = "https://github.com/stm32-rs/stm32f4xx-hal"
+provided_traits = []
+required_traits = []
+mocked_traits = []
+warnings = []
+
+[platforms.hal_info.peripherals]
+gpio = ["GPIOA", "GPIOB"]
+i2c = ["I2C1", "I2C2"]
+uart = ["USART1"]
+interrupts = ["I2C1_EV", "USART1"]
+"#,
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("I2C: I2C1, I2C2"))
+        .stdout(predicate::str::contains("Interrupts: I2C1_EV, USART1"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bsp")
+        .arg("new")
+        .arg("devkit")
+        .arg("--platform")
+        .arg("stm32")
+        .arg("--resource")
+        .arg("SENSOR_BUS=hal::i2c::I2c<hal::pac::I2C3>")
+        .arg("--resource")
+        .arg("CONSOLE=hal::serial::Serial<hal::pac::USART1>")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "SENSOR_BUS uses I2C3, which the stm32 HAL was not seen to provide",
+        ))
+        .stdout(predicate::str::contains("CONSOLE uses").not());
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("i2c = [\n    \"I2C1\",\n    \"I2C2\",\n]"));
+}
+
+/// Test that HAL features chosen at `glue init` end up in the generated HAL and BSP crates
+#[test]
+fn test_hal_features() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    // What `glue init --hal-features stm32f411` records after inspecting the HAL
+    let project_path = temp.path().join("testproj");
+    let glue = fs::read_to_string(project_path.join("glue.toml"))
+        .unwrap()
+        .replace("platforms = []\n", "");
+    let glue = format!(
+        "{}{}",
+        glue,
+        r#"
+[[platforms]]
+name = "stm32"
+target = "thumbv7em-none-eabihf"
+hal_crate = "stm32f4xx-hal"
+hal_features = ["stm32f411"]
+features = []
+
+[platforms.hal_info]
+source = "https://github.com/stm32-rs/stm32f4xx-hal"
+version = "0.21.0"
+provided_traits = []
+required_traits = []
+mocked_traits = []
+warnings = []
+features = ["rt", "stm32f401", "stm32f411", "usb_fs"]
+"#
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .arg("--hal")
+        .arg("stm32f4xx-hal")
+        .assert()
+        .success();
+
+    let dependency = r#"stm32f4xx-hal = { version = "0.21.0", features = ["stm32f411"] }"#;
+    let hal_manifest = fs::read_to_string(project_path.join("hal-stm32/Cargo.toml")).unwrap();
+    assert!(hal_manifest.contains(dependency), "{}", hal_manifest);
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "HAL: stm32f4xx-hal (features: stm32f411)",
+        ));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("bsp")
+        .arg("new")
+        .arg("devkit")
+        .arg("--platform")
+        .arg("stm32")
+        .assert()
+        .success();
+    let bsp_manifest = fs::read_to_string(project_path.join("bsp-devkit/Cargo.toml")).unwrap();
+    assert!(bsp_manifest.contains(dependency), "{}", bsp_manifest);
+}
+
+/// Test that validate adds the critical-section and portable-atomic setup a HAL needs
+#[test]
+fn test_critical_section_requirements() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("m0")
+        .arg("--target")
+        .arg("thumbv6m-none-eabi")
+        .assert()
+        .success();
+
+    // What `glue init` records for a HAL depending on critical-section and portable-atomic
+    let mut glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    glue.push_str(
+        r#"
+[platforms.hal_info]
+source = "https://github.com/example/m0-hal"
+provided_traits = []
+required_traits = []
+mocked_traits = []
+warnings = []
+critical_section = true
+portable_atomic = true
+"#,
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("validate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Added cortex-m with `critical-section-single-core` to app-m0",
+        ))
+        .stdout(predicate::str::contains(
+            "Added portable-atomic with `critical-section` to app-m0",
+        ));
+
+    let manifest = fs::read_to_string(project_path.join("app-m0/Cargo.toml")).unwrap();
+    assert!(manifest.contains(
+        r#"cortex-m = { version = "0.7", features = ["critical-section-single-core"] }"#
+    ));
+    assert!(manifest
+        .contains(r#"portable-atomic = { version = "1", features = ["critical-section"] }"#));
+
+    // A second run finds everything in place
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("glue")
+        .arg("validate")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added").not());
+}
+
+/// Test that glue init falls back to crates.io metadata when the repository can't be read
+#[test]
+fn test_glue_init_registry_fallback() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    // Nothing listens on the proxy, so GitHub, crates.io and docs.rs are all unreachable
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("HTTPS_PROXY", "http://127.0.0.1:9")
+        .arg("glue")
+        .arg("init")
+        .arg("stm32")
+        .arg("https://github.com/stm32-rs/stm32f4xx-hal")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Could not fetch Cargo.toml from repository",
+        ))
+        .stdout(predicate::str::contains(
+            "Reading crates.io metadata for 'stm32f4xx-hal'",
+        ))
+        .stderr(predicate::str::contains(
+            "Could not read 'stm32f4xx-hal' from crates.io or docs.rs",
+        ));
+
+    // A bare crate name goes straight to the registry
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("HTTPS_PROXY", "http://127.0.0.1:9")
+        .arg("glue")
+        .arg("init")
+        .arg("stm32")
+        .arg("stm32f4xx-hal")
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Trying to fetch Cargo.toml").not())
+        .stderr(predicate::str::contains("from crates.io or docs.rs"));
+}
+
+/// Test that vendor copies the workspace dependencies and points cargo at them
+#[test]
+fn test_vendor() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("CARGO_NET_OFFLINE", "true")
+        .arg("vendor")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Pointed .cargo/config.toml at the vendored sources",
+        ));
+
+    assert!(project_path.join("vendor/embedded-hal-mock").exists());
+    let cargo_config = fs::read_to_string(project_path.join(".cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("replace-with = \"vendored-sources\""));
+    assert!(cargo_config.contains("directory = \"vendor\""));
+    // The generated build settings are kept
+    assert!(cargo_config.contains("[profile.release]"));
+
+    let output = std::process::Command::new("cargo")
+        .current_dir(&project_path)
+        .args(["build", "-p", "core-lib", "--offline"])
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Test that tree flags features a platform only gets through feature unification
+#[test]
+fn test_tree_feature_unification() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("add-platform")
+        .arg("stm32")
+        .arg("--target")
+        .arg("thumbv7em-none-eabihf")
+        .assert()
+        .success();
+
+    // The host tests crate enables core-lib's std feature; the app doesn't
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("CARGO_NET_OFFLINE", "true")
+        .arg("tree")
+        .arg("--target")
+        .arg("stm32")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app-stm32 v0.1.0"))
+        .stdout(predicate::str::contains("cortex-m-rt"))
+        .stdout(predicate::str::contains(
+            "core-lib v0.1.0 gets std only from other workspace members",
+        ));
+}
+
+/// Test that validate explains embedded-hal 0.2 and 1.0 ending up in one platform's graph
+#[test]
+fn test_split_embedded_hal_versions() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    for args in [
+        vec!["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"],
+        vec!["new-driver", "legacy"],
+    ] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path).args(args).assert().success();
+    }
+
+    // A driver still on embedded-hal 0.2, used by the app
+    let driver_manifest = project_path.join("drivers/legacy/Cargo.toml");
+    let manifest = fs::read_to_string(&driver_manifest).unwrap().replace(
+        "embedded-hal = { workspace = true }",
+        "embedded-hal = \"0.2\"",
+    );
+    fs::write(&driver_manifest, manifest).unwrap();
+    let app_manifest = project_path.join("app-stm32/Cargo.toml");
+    let manifest = fs::read_to_string(&app_manifest).unwrap().replace(
+        "[dependencies]\n",
+        "[dependencies]\nlegacy = { path = \"../drivers/legacy\" }\n",
+    );
+    fs::write(&app_manifest, manifest).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("CARGO_NET_OFFLINE", "true")
+        .arg("glue")
+        .arg("validate")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "app-stm32 depends on embedded-hal v0.2.7 and v1.0.0",
+        ))
+        .stdout(predicate::str::contains("v0.2.7 only: legacy v0.1.0"))
+        .stdout(predicate::str::contains("embedded-hal-compat"));
+}
+
+/// Test MSRVs are written as rust-version and `check msrv` builds with those toolchains
+#[test]
+#[cfg(unix)]
+fn test_msrv() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--msrv", "1.75"])
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--msrv", "1.80"])
+        .assert()
+        .success();
+
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("rust-version = \"1.75\""));
+    let core_lib = fs::read_to_string(project_path.join("core-lib/Cargo.toml")).unwrap();
+    assert!(core_lib.contains("rust-version.workspace = true"));
+    let app = fs::read_to_string(project_path.join("app-stm32/Cargo.toml")).unwrap();
+    assert!(app.contains("rust-version = \"1.80\""));
+
+    // Stand-ins for rustup (only stable installed) and cargo, logging what they're asked to do
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("calls.log");
+    let fake_rustup = bin_dir.join("rustup");
+    fs::write(
+        &fake_rustup,
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = \"toolchain\" ] && [ \"$2\" = \"list\" ]; then echo 'stable-x86_64-unknown-linux-gnu (default)'; exit 0; fi\necho \"rustup $@\" >> {}\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    let fake_cargo = bin_dir.join("cargo");
+    fs::write(
+        &fake_cargo,
+        format!(
+            "#!/bin/sh\necho \"cargo +$RUSTUP_TOOLCHAIN $@\" >> {}\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    for fake in [&fake_rustup, &fake_cargo] {
+        fs::set_permissions(fake, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .args(["check", "msrv"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Building host crates with Rust 1.75",
+        ))
+        .stdout(predicate::str::contains(
+            "Building app-stm32 with Rust 1.80",
+        ));
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("rustup toolchain install 1.75 --profile minimal"));
+    assert!(calls.contains("rustup target add --toolchain 1.80 thumbv7em-none-eabihf"));
+    assert!(calls.contains("cargo +1.75 build --workspace --exclude app-*"));
+    assert!(calls.contains("cargo +1.80 build --target thumbv7em-none-eabihf -p app-stm32"));
+}
+
+/// Test rust-toolchain.toml pins the channel and targets, and nightly-only platforms use +nightly
+#[test]
+#[cfg(unix)]
+fn test_rust_toolchain_pin() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--toolchain", "1.82.0"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Pinned 1.82.0 in rust-toolchain.toml",
+        ));
+
+    let project_path = temp.path().join("testproj");
+    for args in [
+        ["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"],
+        [
+            "add-platform",
+            "uno",
+            "--target",
+            "avr-unknown-gnu-atmega328",
+        ],
+    ] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path).args(args).assert().success();
+    }
+
+    // AVR is tier 3, so rustup has nothing to install for it
+    let toolchain = fs::read_to_string(project_path.join("rust-toolchain.toml")).unwrap();
+    assert!(toolchain.contains("channel = \"1.82.0\""));
+    assert!(toolchain.contains("components = [\"rustfmt\", \"clippy\"]"));
+    assert!(toolchain.contains("targets = [\"thumbv7em-none-eabihf\"]"));
+
+    // A -Z rustflag needs nightly, which the workspace pin isn't
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap().replace(
+        "name = \"stm32\"\n",
+        "name = \"stm32\"\nrustflags = [\"-Zemit-stack-sizes\"]\n",
+    );
+    fs::write(&glue_path, glue).unwrap();
+
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("cargo.log");
+    let fake_cargo = bin_dir.join("cargo");
+    fs::write(
+        &fake_cargo,
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_cargo, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    for platform in ["stm32", "uno"] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .env("PATH", &path)
+            .args(["check", "--target", platform])
+            .assert()
+            .success();
+    }
+
+    // The AVR app pins nightly in its own rust-toolchain.toml
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("+nightly check --target thumbv7em-none-eabihf -p app-stm32"));
+    assert!(calls.contains("\ncheck --target avr-unknown-gnu-atmega328 -p app-uno"));
+}
+
+/// Test build_std rebuilds core on nightly and checks for rust-src first
+#[test]
+#[cfg(unix)]
+fn test_build_std() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "m33",
+            "--target",
+            "thumbv8m.main-none-eabihf",
+        ])
+        .args(["--build-std", "core,alloc"])
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap().replace(
+        "build_std = [",
+        "build_std_features = [\"panic_immediate_abort\"]\nbuild_std = [",
+    );
+    fs::write(&glue_path, glue).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["glue", "sync"])
+        .assert()
+        .success();
+
+    let cargo_config = fs::read_to_string(project_path.join("app-m33/.cargo/config.toml")).unwrap();
+    assert!(cargo_config.contains("[unstable]"));
+    assert!(cargo_config.contains("build-std = [\n    \"core\",\n    \"alloc\",\n]"));
+    assert!(cargo_config.contains("build-std-features = [\"panic_immediate_abort\"]"));
+    // Nothing for rustup to install: core comes from source
+    let toolchain = fs::read_to_string(project_path.join("rust-toolchain.toml")).unwrap();
+    assert!(toolchain.contains("targets = []"));
+
+    // Stand-ins for rustup (whose component list is swapped below) and cargo
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let components = temp.path().join("components.txt");
+    let log = temp.path().join("cargo.log");
+    let fake_rustup = bin_dir.join("rustup");
+    fs::write(
+        &fake_rustup,
+        format!("#!/bin/sh\ncat {}\n", components.display()),
+    )
+    .unwrap();
+    let fake_cargo = bin_dir.join("cargo");
+    fs::write(
+        &fake_cargo,
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    for fake in [&fake_rustup, &fake_cargo] {
+        fs::set_permissions(fake, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    fs::write(&components, "rustc-x86_64-unknown-linux-gnu\n").unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["check", "--target", "m33"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "rustup component add rust-src --toolchain nightly",
+        ));
+
+    fs::write(&components, "rust-src\n").unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["check", "--target", "m33"])
+        .assert()
+        .success();
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("+nightly check --target thumbv8m.main-none-eabihf -p app-m33"));
+}
+
+/// Test release bumps versions, builds and tests in release mode, collects artifacts and tags
+#[test]
+fn test_release() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--hal", "nb"])
+        .assert()
+        .success();
+
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(&project_path)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    git(&["init", "--quiet"]);
+    git(&["add", "-A"]);
+    git(&["commit", "--quiet", "-m", "Initial commit"]);
+
+    let release = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .arg("release")
+            .args(args)
+            .assert()
+    };
+
+    release(&["v0.2.0", "--dry-run"])
+        .success()
+        .stdout(predicate::str::contains(
+            "Set version 0.2.0 in hal-desk, app-desk, core-lib",
+        ))
+        .stdout(predicate::str::contains("Build the host crates and desk"))
+        .stdout(predicate::str::contains("Tag the release commit v0.2.0"));
+    assert!(git(&["tag", "--list"]).is_empty());
+    assert!(fs::read_to_string(project_path.join("core-lib/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.1.0\""));
+
+    release(&["v0.2"])
+        .failure()
+        .stderr(predicate::str::contains("is not a version like v1.2.0"));
+
+    release(&["v0.2.0"])
+        .success()
+        .stdout(predicate::str::contains("✅ Released v0.2.0"));
+    for crate_dir in ["core-lib", "app-desk"] {
+        let manifest = fs::read_to_string(project_path.join(crate_dir).join("Cargo.toml")).unwrap();
+        assert!(manifest.contains("version = \"0.2.0\""), "{}", crate_dir);
+    }
+    assert_eq!(git(&["tag", "--list"]).trim(), "v0.2.0");
+    assert_eq!(
+        git(&["log", "-1", "--format=%s", "v0.2.0"]).trim(),
+        "Release v0.2.0"
+    );
+
+    let release_dir = project_path.join("artifacts/release/v0.2.0");
+    let sums = fs::read_to_string(release_dir.join("SHA256SUMS")).unwrap();
+    assert!(sums.contains("  desk/desk\n"));
+    assert!(sums.contains("  desk/sbom.cdx.json\n"));
+    let sbom: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(release_dir.join("desk/sbom.cdx.json")).unwrap())
+            .unwrap();
+    assert_eq!(sbom["bomFormat"], "CycloneDX");
+    assert_eq!(sbom["metadata"]["component"]["version"], "0.2.0");
+    assert!(sbom["components"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|component| component["name"] == "embedded-hal"));
+
+    release(&["v0.2.0"])
+        .failure()
+        .stderr(predicate::str::contains("Tag v0.2.0 already exists"));
+}
+
+/// Test flash and deploy --verify read the flash back and report mismatching addresses
+#[test]
+#[cfg(unix)]
+fn test_deploy_verify() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--runner", "probe-rs run --chip STM32F411RETx"])
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\n\n[[devices]]\nname = \"bench-2\"\nplatform = \"desk\"\nprobe = \"0483:374b:0002\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    // A minimal 32-bit ELF with one 8-byte segment loaded at 0x08000000
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    for (value, size) in [
+        (2u32, 2),
+        (0x28, 2),
+        (1, 4),
+        (0x0800_0000, 4),
+        (52, 4),
+        (0, 4),
+        (0, 4),
+        (52, 2),
+        (32, 2),
+        (1, 2),
+        (40, 2),
+        (0, 2),
+        (0, 2),
+        // Program header
+        (1, 4),
+        (84, 4),
+        (0x0800_0000, 4),
+        (0x0800_0000, 4),
+        (8, 4),
+        (8, 4),
+        (5, 4),
+        (4, 4),
+    ] {
+        elf.extend_from_slice(&value.to_le_bytes()[..size]);
+    }
+    elf.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef, 0x01, 0x02, 0x03, 0x04]);
+    let artifacts = project_path.join("artifacts/desk/debug");
+    fs::create_dir_all(&artifacts).unwrap();
+    fs::write(artifacts.join("desk"), &elf).unwrap();
+    fs::write(
+        artifacts.join("manifest.json"),
+        format!(
+            r#"{{"platform": "desk", "target": "x86_64-unknown-linux-gnu", "profile": "debug", "git_rev": null, "artifacts": [{{"name": "desk", "path": "artifacts/desk/debug/desk", "size": {}, "sha256": ""}}]}}"#,
+            elf.len()
+        ),
+    )
+    .unwrap();
+
+    // Stand-in for probe-rs where the second board reads back two bad bytes, after a status line
+    // whose words also parse as hex
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("probe-rs.log");
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> {}\n[ \"$1\" = read ] || exit 0\necho 'Read 8 bytes at add be'\ncase \"$*\" in *0002*) echo 'de ad be ef 01 ff ff 04';; *) echo 'de ad be ef 01 02 03 04';; esac\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["deploy", "--platform", "desk", "--verify"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("✅ bench-1"))
+        .stderr(predicate::str::contains(
+            "❌ bench-2: verify failed at 0x08000005..0x08000006",
+        ));
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("read b8 0x8000000 8 --chip STM32F411RETx --probe 0483:374b:0001"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["flash", "--device", "bench-1", "--verify"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("flash contents verified"));
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["flash", "--device", "bench-2", "--verify"])
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains(
+            "verify failed at 0x08000005..0x08000006",
+        ));
+}
+
+/// Test recover runs the chip's unlock sequence through probe-rs only once confirmed
+#[test]
+#[cfg(unix)]
+fn test_recover() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "nrf", "--target", "thumbv7em-none-eabihf"])
+        .args(["--runner", "probe-rs run --chip nRF52840_xxAA"])
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"nrf\"\nprobe = \"1366:1015:0001\"\n\n[[devices]]\nname = \"bench-2\"\nplatform = \"nrf\"\nchip = \"STM32F411RETx\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("probe-rs.log");
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    // Erasing needs explicit consent when nobody can be asked
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["recover", "--target", "nrf"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pass --yes to confirm"));
+    assert!(!log.exists());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["recover", "--device", "bench-1", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("nRF APPROTECT"));
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["recover", "--device", "bench-2", "--yes"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("STM32 read-out protection"));
+
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains("erase --allow-erase-all --chip nRF52840_xxAA --probe 1366:1015:0001"));
+    assert!(calls.contains("erase --allow-erase-all --chip STM32F411RETx --connect-under-reset"));
+}
+
+/// Test data build/flash/merge turn a settings description into an NVS partition image
+#[test]
+#[cfg(unix)]
+fn test_data_settings_image() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--runner", "probe-rs run --chip STM32F411RETx"])
+        .assert()
+        .success();
+
+    // No settings partition yet
+    fs::write(
+        project_path.join("settings.toml"),
+        r#"format = "tlv"
+
+[[entries]]
+key = "serial"
+type = "u32"
+value = 305419896
+
+[[entries]]
+key = "name"
+type = "str"
+value = "rig"
+
+[[entries]]
+key = "calibration"
+type = "bytes"
+value = "0102"
+id = 7
+"#,
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["data", "build", "stm32", "settings.toml"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("partitions generate stm32"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["partitions", "generate", "stm32"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["data", "build", "stm32", "settings.toml"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("calibration"))
+        .stdout(predicate::str::contains("(id 7)"));
+    let image = fs::read(project_path.join("artifacts/stm32/data/nvs.bin")).unwrap();
+    assert_eq!(image.len(), 16 * 1024);
+    assert_eq!(
+        &image[..23],
+        &[
+            1, 0, 4, 0, 0x78, 0x56, 0x34, 0x12, 2, 0, 3, 0, b'r', b'i', b'g', 7, 0, 2, 0, 1, 2,
+            0xff, 0xff
+        ]
+    );
+    assert!(image[23..].iter().all(|&byte| byte == 0xff));
+
+    // Raw layouts need fixed-size strings
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["data", "build", "stm32", "settings.toml", "--format", "raw"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'name' needs a fixed `len`"));
+
+    // Values that don't fit their type are errors rather than panics or rounding
+    for (kind, value, message) in [
+        ("bytes", "\"aéa\"", "'bad' is not a valid bytes"),
+        ("f32", "1e39", "which an f32 can't hold exactly"),
+        ("f32", "16777217.0", "which an f32 can't hold exactly"),
+    ] {
+        fs::write(
+            project_path.join("bad.toml"),
+            format!(
+                "[[entries]]\nkey = \"bad\"\ntype = \"{}\"\nvalue = {}\n",
+                kind, value
+            ),
+        )
+        .unwrap();
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .args(["data", "build", "stm32", "bad.toml"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(message));
+    }
+    fs::write(
+        project_path.join("bad.toml"),
+        "[[entries]]\nkey = \"gain\"\ntype = \"f32\"\nvalue = 0.1\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["data", "build", "stm32", "bad.toml"])
+        .assert()
+        .success();
+
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("probe-rs.log");
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["data", "flash", "stm32", "settings.toml"])
+        .assert()
+        .success();
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains(&format!(
+        "download --binary-format bin --base-address 0x8038000 --chip STM32F411RETx {}",
+        project_path.join("artifacts/stm32/data/nvs.bin").display()
+    )));
+
+    // Merge into a one-segment firmware image at the start of flash
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    for (value, size) in [
+        (2u32, 2),
+        (0x28, 2),
+        (1, 4),
+        (0x0800_0000, 4),
+        (52, 4),
+        (0, 4),
+        (0, 4),
+        (52, 2),
+        (32, 2),
+        (1, 2),
+        (40, 2),
+        (0, 2),
+        (0, 2),
+        (1, 4),
+        (84, 4),
+        (0x0800_0000, 4),
+        (0x0800_0000, 4),
+        (4, 4),
+        (4, 4),
+        (5, 4),
+        (4, 4),
+    ] {
+        elf.extend_from_slice(&value.to_le_bytes()[..size]);
+    }
+    elf.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+    let artifacts = project_path.join("artifacts/stm32/debug");
+    fs::create_dir_all(&artifacts).unwrap();
+    fs::write(artifacts.join("stm32"), &elf).unwrap();
+    fs::write(
+        artifacts.join("manifest.json"),
+        r#"{"platform": "stm32", "target": "thumbv7em-none-eabihf", "profile": "debug", "git_rev": null, "artifacts": [{"name": "stm32", "path": "artifacts/stm32/debug/stm32", "size": 88, "sha256": ""}]}"#,
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["data", "merge", "stm32", "settings.toml"])
+        .assert()
+        .success();
+    let merged = fs::read(artifacts.join("stm32-nvs.bin")).unwrap();
+    assert_eq!(merged.len(), 240 * 1024);
+    assert_eq!(&merged[..4], &[0xde, 0xad, 0xbe, 0xef]);
+    assert_eq!(merged[4], 0xff);
+    assert_eq!(&merged[224 * 1024..], &image[..]);
+}
+
+/// Test fs build/flash pack a directory into a littlefs image for the data partition
+#[test]
+#[cfg(unix)]
+fn test_fs_littlefs_image() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--runner", "probe-rs run --chip STM32F411RETx"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["partitions", "generate", "stm32"])
+        .assert()
+        .success();
+    fs::create_dir_all(project_path.join("assets/www")).unwrap();
+    fs::write(project_path.join("assets/www/index.html"), "<h1>hi</h1>").unwrap();
+    fs::write(project_path.join("assets/model.bin"), [0u8; 100]).unwrap();
+
+    // Stand-ins for mklittlefs (filling the image to its -s size) and probe-rs
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("tools.log");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &bin_dir)
+        .args(["fs", "build", "--target", "stm32"])
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("mklittlefs is not installed"))
+        .stderr(predicate::str::contains(
+            "https://github.com/earlephilhower/mklittlefs/releases",
+        ));
+
+    let fake_mklittlefs = bin_dir.join("mklittlefs");
+    fs::write(
+        &fake_mklittlefs,
+        format!(
+            "#!/bin/sh\necho mklittlefs \"$@\" >> {}\nwhile [ $# -gt 1 ]; do [ \"$1\" = -s ] && size=$2; shift; done\nhead -c $size /dev/zero > \"$1\"\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        format!("#!/bin/sh\necho probe-rs \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    for fake in [&fake_mklittlefs, &fake_probe_rs] {
+        fs::set_permissions(fake, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["fs", "build", "--target", "stm32", "--block-size", "3000"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "not a whole number of 3000-byte blocks",
+        ));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["fs", "build", "--target", "stm32"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("(2 files, 111 bytes)"));
+    let image = project_path.join("artifacts/stm32/fs/user_data.bin");
+    assert_eq!(fs::metadata(&image).unwrap().len(), 16 * 1024);
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["fs", "flash", "--target", "stm32"])
+        .assert()
+        .success();
+    let calls = fs::read_to_string(&log).unwrap();
+    assert!(calls.contains(&format!(
+        "mklittlefs -c {} -b 4096 -p 256 -s 16384 {}",
+        project_path.join("assets").display(),
+        image.display()
+    )));
+    assert!(calls.contains(&format!(
+        "probe-rs download --binary-format bin --base-address 0x803c000 --chip STM32F411RETx {}",
+        image.display()
+    )));
+}
+
+/// Test [[assets]] generate an app module of aligned, optionally compressed byte statics
+#[test]
+fn test_assets_module() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--hal", "nb"])
+        .assert()
+        .success();
+
+    fs::create_dir_all(project_path.join("assets")).unwrap();
+    fs::write(project_path.join("assets/logo.bin"), [1u8, 2, 3]).unwrap();
+    let font = "glyph ".repeat(200);
+    fs::write(project_path.join("assets/font.bin"), &font).unwrap();
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[assets]]\nname = \"logo\"\npath = \"assets/logo.bin\"\n\n[[assets]]\nname = \"font\"\npath = \"assets/font.bin\"\nmodule = \"fonts\"\ncompress = \"deflate\"\nalign = 4\n\n[[assets]]\nname = \"cert\"\npath = \"assets/missing.pem\"\nplatforms = [\"other\"]\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["glue", "sync"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "app-desk/src/assets.rs (2 assets)",
+        ))
+        .stdout(predicate::str::contains("Add `mod assets;`"));
+
+    let module = fs::read_to_string(project_path.join("app-desk/src/assets.rs")).unwrap();
+    assert!(module.contains("pub static LOGO: &[u8] = include_bytes!(\"../../assets/logo.bin\");"));
+    assert!(module.contains("pub mod fonts {"));
+    assert!(module.contains("pub const FONT_LEN: usize = 1200;"));
+    assert!(module.contains("&super::Align4(*include_bytes!(\"assets/font.deflate\"))"));
+    assert!(!module.contains("CERT"));
+    let packed = fs::read(project_path.join("app-desk/src/assets/font.deflate")).unwrap();
+    assert!(packed.len() < font.len());
+    assert_eq!(
+        miniz_oxide::inflate::decompress_to_vec(&packed).unwrap(),
+        font.as_bytes()
+    );
+
+    // The generated module compiles into the app
+    let main_path = project_path.join("app-desk/src/main.rs");
+    let main = fs::read_to_string(&main_path).unwrap();
+    fs::write(&main_path, format!("mod assets;\n{}", main)).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk"])
+        .assert()
+        .success();
+}
+
+/// proto generate writes shared message crates whose round-trip tests pass
+#[test]
+fn test_proto_roundtrip() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--hal", "nb"])
+        .assert()
+        .success();
+
+    // An unbounded postcard string is rejected
+    fs::create_dir_all(project_path.join("proto")).unwrap();
+    let schema_path = project_path.join("proto/messages.toml");
+    fs::write(
+        &schema_path,
+        "[[messages]]\nname = \"Log\"\nfields = [{ name = \"text\", type = \"string\" }]\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["proto", "generate"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Log.text: postcard strings and bytes need a max_len",
+        ));
+
+    fs::write(
+        &schema_path,
+        "[[enums]]\nname = \"Mode\"\nvariants = [\"Idle\", \"Run\"]\n\n\
+         [[messages]]\nname = \"Header\"\nfields = [{ name = \"seq\", type = \"u16\" }, { name = \"mode\", type = \"Mode\" }]\n\n\
+         [[messages]]\nname = \"Packet\"\nfields = [\n    { name = \"header\", type = \"Header\" },\n    \
+         { name = \"payload\", type = \"bytes\", max_len = 32 },\n    \
+         { name = \"note\", type = \"string\", max_len = 8, optional = true },\n]\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["proto", "generate"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Generating postcard message code for 2 messages",
+        ))
+        .stdout(predicate::str::contains("app-desk depends on protocol"));
+
+    let device = fs::read_to_string(project_path.join("protocol/src/lib.rs")).unwrap();
+    assert!(device.contains("#![no_std]"));
+    assert!(device.contains("pub payload: heapless::Vec<u8, 32>,"));
+    assert!(device.contains("pub note: Option<heapless::String<8>>,"));
+    assert!(device.contains("    Packet(Packet),"));
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"protocol-host\""));
+    let app = fs::read_to_string(project_path.join("app-desk/Cargo.toml")).unwrap();
+    assert_eq!(
+        app.matches("protocol = { path = \"../protocol\" }").count(),
+        1
+    );
+
+    // The generated round-trip tests pass on the host
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["test", "--", "packet_round_trips"])
+        .assert()
+        .success();
+}
+
+/// hil serial-test runs send/expect scripts against a device's port and reports per test
+#[test]
+fn test_hil_serial_test() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    // A plain file stands in for the port: replies are read from the start, sends are appended
+    let project_path = temp.path().join("testproj");
+    let port = project_path.join("port.log");
+    fs::write(&port, "boot: ready\r\nPONG\r\nVERSION 1.2.0\r\n").unwrap();
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nport = \"{}\"\n",
+            glue,
+            port.display()
+        ),
+    )
+    .unwrap();
+    fs::create_dir_all(project_path.join("hil")).unwrap();
+    fs::write(
+        project_path.join("hil/smoke.toml"),
+        "timeout_ms = 500\n\n\
+         [[tests]]\nname = \"ping\"\nsteps = [{ send = \"PING\", expect = \"PONG\" }]\n\n\
+         [[tests]]\nname = \"version\"\nsteps = [{ send = \"VERSION?\", expect = \"VERSION 1.2.0\" }]\n\n\
+         [[tests]]\nname = \"reboot\"\nsteps = [{ send = \"REBOOT\", expect = \"boot: ready\", timeout_ms = 200 }]\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["hil", "serial-test", "hil/smoke.toml", "--device", "bench-1"])
+        .args(["--report", "junit:target/hil.xml"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("test ping ... ok"))
+        .stdout(predicate::str::contains("test version ... ok"))
+        .stdout(predicate::str::contains("test reboot ... FAILED"))
+        .stderr(predicate::str::contains(
+            "reboot: step 1: expected \"boot: ready\" within 200 ms; received [\"PING\", \"VERSION?\", \"REBOOT\"]",
+        ))
+        .stderr(predicate::str::contains("1 of 3 serial tests failed"));
+
+    let sent = fs::read_to_string(&port).unwrap();
+    assert!(sent.ends_with("PING\nVERSION?\nREBOOT\n"));
+    let xml = fs::read_to_string(project_path.join("target/hil.xml")).unwrap();
+    assert!(xml.contains("<testsuite name=\"smoke\" tests=\"3\" failures=\"1\""));
+    assert!(xml.contains("<testcase classname=\"smoke\" name=\"ping\"/>"));
+
+    // Non-ASCII hex fails the step instead of panicking on a char boundary
+    fs::write(
+        project_path.join("hil/hex.toml"),
+        "timeout_ms = 200\n\n\
+         [[tests]]\nname = \"send\"\nsteps = [{ send_hex = \"aé1\" }]\n\n\
+         [[tests]]\nname = \"expect\"\nsteps = [{ send = \"PING\", expect_hex = \"aé1\" }]\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["hil", "serial-test", "hil/hex.toml", "--device", "bench-1"])
+        .assert()
+        .code(6)
+        .stdout(predicate::str::contains("test send ... FAILED"))
+        .stdout(predicate::str::contains("test expect ... FAILED"))
+        .stderr(predicate::str::contains("send: step 1: 'aé1' is not hex"))
+        .stderr(predicate::str::contains("expect: step 1: 'aé1' is not hex"));
+}
+
+/// mock from-capture turns a sigrok I2C decode into expectations the host tests can replay
+#[test]
+fn test_mock_from_capture() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    fs::write(
+        project_path.join("sensor-boot.txt"),
+        "i2c-1: Start\ni2c-1: Address write: 48\ni2c-1: Write\ni2c-1: ACK\ni2c-1: Data write: 00\ni2c-1: ACK\n\
+         i2c-1: Start repeat\ni2c-1: Address read: 48\ni2c-1: Read\ni2c-1: ACK\ni2c-1: Data read: 1A\ni2c-1: ACK\n\
+         i2c-1: Data read: 80\ni2c-1: NACK\ni2c-1: Stop\n\
+         i2c-1: Start\ni2c-1: Address write: 48\ni2c-1: Data write: 01\ni2c-1: Data write: 60\ni2c-1: Stop\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["mock", "from-capture", "sensor-boot.txt", "--bus", "i2c"])
+        .args(["--output", "tests/capture.rs"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Wrote sensor_boot_expectations() to tests/capture.rs",
+        ));
+    let code = fs::read_to_string(project_path.join("tests/capture.rs")).unwrap();
+    assert!(code.contains("Transaction::write_read(0x48, vec![0x00], vec![0x1A, 0x80]),"));
+    assert!(code.contains("Transaction::write(0x48, vec![0x01, 0x60]),"));
+
+    // SPI needs the Saleae CSV export, whose chip-select rows delimit transfers
+    fs::write(
+        project_path.join("flash-id.csv"),
+        "name,type,start_time,duration,mosi,miso\n\"SPI\",\"enable\",0.1,0,,\n\"SPI\",\"result\",0.1,0,0x9F,0x00\n\"SPI\",\"result\",0.1,0,0x00,0xEF\n\"SPI\",\"disable\",0.1,0,,\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["mock", "from-capture", "flash-id.csv", "--bus", "spi"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Transaction::transfer_in_place(vec![0x9F, 0x00], vec![0x00, 0xEF]),",
+        ));
+
+    // The expectations replay in the host test suite
+    let manifest_path = project_path.join("tests/Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(
+        &manifest_path,
+        manifest.replace(
+            "[dependencies]\n",
+            "[dependencies]\nembedded-hal = { workspace = true }\n",
+        ),
+    )
+    .unwrap();
+    let test_path = project_path.join("tests/integration_test.rs");
+    let tests = fs::read_to_string(&test_path).unwrap();
+    fs::write(
+        &test_path,
+        format!(
+            "{}\nmod capture;\n\n#[test]\nfn replays_capture() {{\n    use embedded_hal::i2c::I2c;\n    let expectations = capture::sensor_boot_expectations();\n    let mut i2c = embedded_hal_mock::eh1::i2c::Mock::new(&expectations);\n    let mut buf = [0u8; 2];\n    i2c.write_read(0x48, &[0x00], &mut buf).unwrap();\n    assert_eq!(buf, [0x1A, 0x80]);\n    i2c.write(0x48, &[0x01, 0x60]).unwrap();\n    i2c.done();\n}}\n",
+            tests
+        ),
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["test", "--", "replays_capture"])
+        .assert()
+        .success();
+}
+
+/// bench --on-target scaffolds the cycle-counting crate and compares runs against a baseline
+#[cfg(unix)]
+#[test]
+fn test_bench_on_target() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--chip", "STM32F411RETx"])
+        .assert()
+        .success();
+
+    // Stand-in for cargo run + probe-rs, printing the cycle counts from cycles.txt
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("cargo.log");
+    let cycles = temp.path().join("cycles.txt");
+    let fake = bin_dir.join("cargo");
+    fs::write(
+        &fake,
+        format!(
+            "#!/bin/sh\necho \"cargo $@\" >> {}\nwhile read name count; do echo \"INFO  bench $name $count cycles\"; done < {}\n",
+            log.display(),
+            cycles.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    fs::write(&cycles, "application_tick 120\ncrc32_256 4000\n").unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args([
+            "bench",
+            "--target",
+            "stm32",
+            "--on-target",
+            "--save-baseline",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created bench-target-stm32"))
+        .stdout(predicate::str::contains(
+            "application_tick         120  (no baseline)",
+        ))
+        .stdout(predicate::str::contains(
+            "Saved baseline to bench-target-stm32/baseline.json",
+        ));
+
+    let crate_path = project_path.join("bench-target-stm32");
+    let main = fs::read_to_string(crate_path.join("src/main.rs")).unwrap();
+    assert!(main.contains("peripherals.DWT.enable_cycle_counter();"));
+    assert!(main.contains("bench(\"application_tick\", || app.tick());"));
+    let config = fs::read_to_string(crate_path.join(".cargo/config.toml")).unwrap();
+    assert!(config.contains("link-arg=-Tdefmt.x"));
+    let baseline: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(crate_path.join("baseline.json")).unwrap())
+            .unwrap();
+    assert_eq!(baseline["crc32_256"], 4000);
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("\"bench-target-stm32\""));
+    let log_content = fs::read_to_string(&log).unwrap();
+    assert!(
+        log_content.contains("run --release --target thumbv7em-none-eabihf -p bench-target-stm32")
+    );
+
+    // A slower run beyond the limit fails, naming the regressed benchmark
+    fs::write(&cycles, "application_tick 121\ncrc32_256 4800\n").unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["bench", "--target", "stm32", "--on-target"])
+        .args(["--max-regression", "5"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("(baseline 120, +0.8%)"))
+        .stdout(predicate::str::contains("(baseline 4000, +20.0%)"))
+        .stderr(predicate::str::contains(
+            "Over the 5% regression limit: crc32_256 +20.0%",
+        ));
+}
+
+/// profile enables SWO PC sampling through probe-rs and folds the samples by function
+#[cfg(unix)]
+#[test]
+fn test_profile_swo_samples() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--runner", "probe-rs run --chip STM32F411RETx"])
+        .assert()
+        .success();
+
+    // A 32-bit ARM ELF with only a symbol table: a mangled crc32 and main, both Thumb
+    let strings = b"\0_ZN8core_lib5crc3217h0123456789abcdefE\0main\0";
+    let mut symtab = vec![0u8; 16];
+    for (name, value, size) in [(1u32, 0x0800_0101u32, 0x40u32), (40, 0x0800_0201, 0x20)] {
+        for field in [name, value, size] {
+            symtab.extend_from_slice(&field.to_le_bytes());
+        }
+        symtab.extend_from_slice(&[0x12, 0, 1, 0]);
+    }
+    let symtab_offset = 52 + strings.len() as u32;
+    let shoff = symtab_offset + symtab.len() as u32;
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    for (value, size) in [
+        (2u32, 2),
+        (40, 2),
+        (1, 4),
+        (0, 4),
+        (0, 4),
+        (shoff, 4),
+        (0, 4),
+        (52, 2),
+        (0, 2),
+        (0, 2),
+        (40, 2),
+        (3, 2),
+        (0, 2),
+    ] {
+        elf.extend_from_slice(&value.to_le_bytes()[..size]);
+    }
+    elf.extend_from_slice(strings);
+    elf.extend_from_slice(&symtab);
+    elf.extend_from_slice(&[0u8; 40]);
+    for header in [
+        [0, 2, 0, 0, symtab_offset, symtab.len() as u32, 2, 0, 4, 16],
+        [0, 3, 0, 0, 52, strings.len() as u32, 0, 0, 1, 0],
+    ] {
+        for field in header {
+            elf.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    let artifacts = project_path.join("artifacts/desk/debug");
+    fs::create_dir_all(&artifacts).unwrap();
+    fs::write(artifacts.join("desk"), &elf).unwrap();
+    fs::write(
+        artifacts.join("manifest.json"),
+        r#"{"platform": "desk", "target": "x86_64-unknown-linux-gnu", "profile": "debug", "git_rev": null, "artifacts": [{"name": "desk", "path": "artifacts/desk/debug/desk", "size": 0, "sha256": ""}]}"#,
+    )
+    .unwrap();
+
+    // ITM: sync, three samples in crc32, one in main, one asleep, one outside any function,
+    // with an overflow, a stimulus write and a multi-byte timestamp in between
+    let mut capture = vec![0, 0, 0, 0, 0, 0x80];
+    for pc in [
+        0x0800_0110u32,
+        0x0800_0110,
+        0x0800_0204,
+        0x0800_0130,
+        0x0900_0000,
+    ] {
+        capture.push(0x17);
+        capture.extend_from_slice(&pc.to_le_bytes());
+    }
+    capture.extend_from_slice(&[0x70, 0x01, b'A', 0xc0, 0x85, 0x01, 0x15, 0x00]);
+    let swo = temp.path().join("swo.bin");
+    fs::write(&swo, &capture).unwrap();
+
+    // Stand-in for probe-rs accepting the register writes
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("probe-rs.log");
+    let fake = bin_dir.join("probe-rs");
+    fs::write(
+        &fake,
+        format!("#!/bin/sh\necho \"$@\" >> {}\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["profile", "--target", "desk", "--duration", "1"])
+        .args(["--swo-port", swo.to_str().unwrap(), "--clock", "16000000"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("6 samples on desk"))
+        .stdout(predicate::str::contains("50.0%       3  core_lib::crc32"))
+        .stdout(predicate::str::contains("16.7%       1  [sleep]"));
+
+    let writes = fs::read_to_string(&log).unwrap();
+    assert!(writes.contains("write b32 0xe0040010 0x7 --chip STM32F411RETx"));
+    assert!(writes.contains("write b32 0xe0001000 0x161f --chip STM32F411RETx"));
+    let folded = fs::read_to_string(project_path.join("artifacts/desk/profile.folded")).unwrap();
+    assert!(folded.contains("desk;core_lib::crc32 3\n"));
+    assert!(folded.contains("desk;main 1\n"));
+    assert!(folded.contains("desk;[unknown] 1\n"));
+    assert_eq!(
+        fs::read(project_path.join("artifacts/desk/profile.swo")).unwrap(),
+        capture
+    );
+
+    // A saved capture is symbolized again without touching the probe
+    fs::remove_file(&log).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args([
+            "profile",
+            "--target",
+            "desk",
+            "--input",
+            "artifacts/desk/profile.swo",
+        ])
+        .args(["--output", "hot.folded"])
+        .assert()
+        .success();
+    assert!(!log.exists());
+    assert!(fs::read_to_string(project_path.join("hot.folded"))
+        .unwrap()
+        .contains("desk;core_lib::crc32 3\n"));
+}
+
+/// measure latency generates the pend-and-time crate and summarises the reported cycles
+#[cfg(unix)]
+#[test]
+fn test_measure_latency() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "m0", "--target", "thumbv6m-none-eabi"])
+        .args(["--chip", "STM32F030R8Tx"])
+        .assert()
+        .success();
+
+    // Stand-in for cargo run + probe-rs printing the firmware's defmt lines
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let log = temp.path().join("cargo.log");
+    let fake = bin_dir.join("cargo");
+    fs::write(
+        &fake,
+        format!(
+            "#!/bin/sh\necho \"cargo $@\" >> {}\nfor c in 16 16 18 24; do echo \"INFO  latency $c cycles\"; done\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&fake, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["measure", "latency", "--target", "m0", "--irq", "EXTI0_1"])
+        .args(["--samples", "4", "--priority", "64", "--clock", "8000000"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Created latency-target-m0"))
+        .stdout(predicate::str::contains(
+            "min           16 cycles (2.000 µs)",
+        ))
+        .stdout(predicate::str::contains(
+            "avg         18.5 cycles (2.312 µs)",
+        ))
+        .stdout(predicate::str::contains(
+            "jitter         8 cycles (1.000 µs)",
+        ));
+
+    let crate_path = project_path.join("latency-target-m0");
+    let main = fs::read_to_string(crate_path.join("src/main.rs")).unwrap();
+    assert!(main.contains("#[interrupt]\nfn EXTI0_1() {"));
+    assert!(main.contains("peripherals.NVIC.set_priority(Interrupt::EXTI0_1, 64)"));
+    assert!(main.contains("for _ in 0..4 {"));
+    // Cortex-M0 has no DWT cycle counter
+    assert!(main.contains("syst.set_reload(0x00ff_ffff);"));
+    assert!(crate_path.join("src/pin.rs").exists());
+    let log_content = fs::read_to_string(&log).unwrap();
+    assert!(log_content.contains("run --release --target thumbv6m-none-eabi -p latency-target-m0"));
+    let results: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(project_path.join("artifacts/m0/latency.json")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(results["EXTI0_1"]["max"], 24);
+
+    // A second run rewrites main.rs for the new interrupt but keeps the pin hooks
+    fs::write(crate_path.join("src/pin.rs"), "// scope pin\n").unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", &path)
+        .args(["measure", "latency", "--target", "m0", "--irq", "TIM3"])
+        .assert()
+        .success();
+    let main = fs::read_to_string(crate_path.join("src/main.rs")).unwrap();
+    assert!(main.contains("NVIC::pend(Interrupt::TIM3);"));
+    assert!(!main.contains("set_priority"));
+    assert_eq!(
+        fs::read_to_string(crate_path.join("src/pin.rs")).unwrap(),
+        "// scope pin\n"
+    );
+}
+
+/// `add-platform --rtos freertos` wires freertos-rust, the kernel build and a task running core-lib
+#[test]
+fn test_add_platform_freertos() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--rtos", "freertos"])
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("rtos = \"freertos\""));
+
+    let app = project_path.join("app-stm32");
+    let manifest = fs::read_to_string(app.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("freertos-rust = \"0.1\""));
+    assert!(manifest.contains("[build-dependencies]\nfreertos-cargo-build"));
+
+    let build = fs::read_to_string(app.join("build.rs")).unwrap();
+    assert!(build.contains("freertos_port(\"GCC/ARM_CM4F\""));
+    assert!(build.contains("include_bytes!(\"memory.x\")"));
+    let header = fs::read_to_string(app.join("freertos/FreeRTOSConfig.h")).unwrap();
+    assert!(header.contains("#define xPortSysTickHandler SysTick"));
+
+    let main = fs::read_to_string(app.join("src/main.rs")).unwrap();
+    assert!(main.contains("static GLOBAL: FreeRtosAllocator"));
+    assert!(main.contains("Task::new()"));
+    assert!(main.contains("FreeRtosUtils::start_scheduler()"));
+
+    // The kernel ports are Cortex-M only, and the FreeRTOS heap replaces --alloc
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "c3",
+            "--preset",
+            "esp32c3",
+            "--rtos",
+            "freertos",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("ESP-IDF"));
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "m0", "--target", "thumbv6m-none-eabi"])
+        .args(["--rtos", "freertos", "--alloc", "embedded-alloc"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("drop --alloc"));
+}
+
+/// `bsp generate` wires an embassy platform's main from the board's resources in glue.toml
+#[test]
+fn test_embassy_board_wiring() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--hal", "embassy-stm32", "--rtos", "embassy"])
+        .assert()
+        .success();
+
+    let app = project_path.join("app-stm32");
+    let manifest = fs::read_to_string(app.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("\"arch-cortex-m\""));
+    assert!(manifest.contains("critical-section-single-core"));
+    let main = fs::read_to_string(app.join("src/main.rs")).unwrap();
+    assert!(main.contains("#[embassy_executor::main]"));
+    assert!(main.contains("spawner.spawn(app()).unwrap();"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["bsp", "new", "nucleo", "--platform", "stm32"])
+        .args(["--resource", "LED1=hal::gpio::Output<'static>"])
+        .args(["--resource", "BUTTON=hal::gpio::Input<'static>"])
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("peripherals = \"hal::init(Default::default())\""));
+    let glue = glue
+        .replace(
+            "name = \"LED1\"",
+            "name = \"LED1\"\ninit = \"hal::gpio::Output::new(p.PA5, hal::gpio::Level::Low, hal::gpio::Speed::Low)\"",
+        )
+        .replace(
+            "name = \"BUTTON\"",
+            "name = \"BUTTON\"\ninit = \"hal::gpio::Input::new(p.PC13, hal::gpio::Pull::None)\"",
+        );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["bsp", "generate", "nucleo"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wired nucleo's resources"));
+
+    let manifest = fs::read_to_string(app.join("Cargo.toml")).unwrap();
+    assert_eq!(manifest.matches("bsp-nucleo = { path").count(), 1);
+    let main = fs::read_to_string(app.join("src/main.rs")).unwrap();
+    assert!(main.contains("let p = hal::init(Default::default());"));
+    assert!(main.contains("let led1 = hal::gpio::Output::new(p.PA5"));
+    assert!(main.contains("let led = hal_stm32::STM32Led::new(led1);"));
+    assert!(main.contains("spawner.spawn(app(led, button)).unwrap();"));
+    assert!(main.contains(
+        "async fn app(led: hal_stm32::STM32Led<bsp_nucleo::Led1>, _button: bsp_nucleo::Button)"
+    ));
+    assert!(main.contains("core_lib::Application::new(led)"));
+}
+
+/// An RTIC platform's #[shared]/#[local] resources and init follow the board in glue.toml
+#[test]
+fn test_rtic_board_resources() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .args(["--rtos", "rtic"])
+        .assert()
+        .success();
+
+    let app = project_path.join("app-stm32");
+    let manifest = fs::read_to_string(app.join("Cargo.toml")).unwrap();
+    assert!(manifest.contains("rtic = { version = \"2\", features = [\"thumbv7-backend\"] }"));
+    assert!(manifest.contains("\"cortex-m-systick\""));
+    let main = fs::read_to_string(app.join("src/main.rs")).unwrap();
+    assert!(main.contains("#[rtic::app(device = stm32f4xx_hal::pac, dispatchers = [EXTI1])]"));
+    assert!(main.contains("systick_monotonic!(Mono, 1_000);"));
+    assert!(main.contains("struct Shared {}"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["bsp", "new", "nucleo", "--platform", "stm32"])
+        .args(["--resource", "LED1=hal::gpio::PA5<hal::gpio::Output>"])
+        .args(["--resource", "SERIAL=hal::serial::Serial<hal::pac::USART2>"])
+        .assert()
+        .success();
+
+    // Resources without an init fail the build rather than panicking at boot
+    let main = fs::read_to_string(app.join("src/main.rs")).unwrap();
+    assert!(main.contains(
+        "compile_error!(\"set `init` for LED1 in glue.toml and run `multi-target-rs bsp generate nucleo`\")"
+    ));
+
+    // Changing the pin map in glue.toml regenerates the resources
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    let glue = glue
+        .replace(
+            "name = \"LED1\"",
+            "name = \"LED1\"\ninit = \"gpioa.pa5.into_push_pull_output()\"",
+        )
+        .replace(
+            "name = \"SERIAL\"",
+            "name = \"SERIAL\"\ninit = \"serial_from(p.USART2)\"\nshared = true",
+        );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["bsp", "generate", "nucleo"])
+        .assert()
+        .success();
+
+    let main = fs::read_to_string(app.join("src/main.rs")).unwrap();
+    assert!(main.contains("        serial: bsp_nucleo::Serial,\n"));
+    assert!(main
+        .contains("        app: core_lib::Application<hal_stm32::STM32Led<bsp_nucleo::Led1>>,\n"));
+    assert!(main.contains("let p = cx.device;"));
+    assert!(main.contains("let app = core_lib::Application::new(hal_stm32::STM32Led::new(led1));"));
+    assert!(main.contains("(Shared { serial }, Local { app })"));
+    assert!(main.contains("#[task(local = [app], shared = [serial])]"));
+    assert!(!main.contains("todo!"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "c3",
+            "--preset",
+            "esp32c3",
+            "--rtos",
+            "rtic",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Cortex-M backends"));
+}
+
+/// `secrets add` generates a secrets crate that fails the build clearly until the value is provided
+#[test]
+fn test_secrets_injection() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    // Keep the build on the toolchain running this test
+    let _ = fs::remove_file(project_path.join("rust-toolchain.toml"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "host",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--hal", "nb"])
+        .assert()
+        .success();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "secrets",
+            "add",
+            "WIFI_PSK",
+            "--description",
+            "Wi-Fi password",
+        ])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["secrets", "add", "API_KEY", "--optional"])
+        .assert()
+        .success();
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("[[secrets]]\nname = \"WIFI_PSK\""));
+    let gitignore = fs::read_to_string(project_path.join(".gitignore")).unwrap();
+    assert!(gitignore.contains("/secrets.toml"));
+    let lib = fs::read_to_string(project_path.join("secrets/src/lib.rs")).unwrap();
+    assert!(lib.contains("pub const WIFI_PSK: &str = env!(\"SECRET_WIFI_PSK\");"));
+    assert!(lib.contains("pub const API_KEY: Option<&str> = option_env!(\"SECRET_API_KEY\");"));
+    let manifest = fs::read_to_string(project_path.join("app-host/Cargo.toml")).unwrap();
+    assert!(manifest.contains("secrets = { path = \"../secrets\" }"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["secrets", "check"])
+        .env_remove("WIFI_PSK")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("❌ WIFI_PSK is not set"));
+
+    let mut cargo = std::process::Command::new("cargo");
+    cargo
+        .current_dir(&project_path)
+        .args(["build", "-p", "secrets"])
+        .env_remove("WIFI_PSK");
+    let output = cargo.output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("secret WIFI_PSK is not set (Wi-Fi password)"));
+
+    // The untracked file supplies the value; it never reaches glue.toml
+    fs::write(
+        project_path.join("secrets.toml"),
+        "WIFI_PSK = \"hunter2\"\n",
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["secrets", "check"])
+        .env_remove("WIFI_PSK")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("✓ WIFI_PSK (from secrets.toml)"))
+        .stdout(predicate::str::contains("hunter2").not());
+    let mut cargo = std::process::Command::new("cargo");
+    cargo
+        .current_dir(&project_path)
+        .args(["build", "-p", "secrets"])
+        .env_remove("WIFI_PSK");
+    assert!(cargo.status().unwrap().success());
+}
+
+/// `build --variant` applies the variant's features, env, profile settings and artifact directory
+#[test]
+fn test_build_variant() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .args(["--hal", "nb"])
+        .assert()
+        .success();
+
+    let mut glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    glue.push_str(
+        r#"
+[platforms.variants.prod]
+features = ["telemetry"]
+release = true
+
+[platforms.variants.prod.env]
+SERVER_URL = "https://prod.example.com"
+
+[platforms.variants.prod.profile]
+opt-level = "s"
+"#,
+    );
+    fs::write(project_path.join("glue.toml"), glue).unwrap();
+    let manifest_path = project_path.join("app-desk/Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(
+        &manifest_path,
+        manifest.replace("[[bin]]", "[features]\ntelemetry = []\n\n[[bin]]"),
+    )
+    .unwrap();
+    fs::write(
+        project_path.join("app-desk/src/main.rs"),
+        "fn main() {\n    println!(\"{} telemetry={}\", env!(\"SERVER_URL\"), cfg!(feature = \"telemetry\"));\n}\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk", "--variant", "staging"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("known: prod"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk", "--variant", "prod"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "artifacts/desk/prod/release/manifest.json",
+        ));
+
+    let manifest: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(project_path.join("artifacts/desk/prod/release/manifest.json"))
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(manifest["variant"], "prod");
+    assert_eq!(manifest["profile"], "release");
+    assert_eq!(manifest["artifacts"][0]["name"], "desk");
+    let binary = project_path.join("target/x86_64-unknown-linux-gnu/release/desk");
+    let output = std::process::Command::new(binary).output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "https://prod.example.com telemetry=true\n"
+    );
+}
+
+/// A failing add-platform rolls the project back, and --force replaces a platform with a backup
+#[test]
+fn test_add_platform_transaction() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--msrv", "1.75"])
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let workspace_path = project_path.join("Cargo.toml");
+    let glue_before = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+
+    // Files the command doesn't write are left alone by the rollback
+    fs::create_dir_all(project_path.join("vendor/foo")).unwrap();
+    fs::write(project_path.join("vendor/foo/lib.rs"), "// vendored\n").unwrap();
+    fs::write(project_path.join("notes.txt"), "mine\n").unwrap();
+
+    // The workspace manifest only fails to parse after the crates have been written
+    let workspace = fs::read_to_string(&workspace_path).unwrap();
+    fs::write(&workspace_path, format!("{}[[[broken\n", workspace)).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("Rolled back"));
+    assert!(!project_path.join("app-stm32").exists());
+    assert!(!project_path.join("hal-stm32").exists());
+    assert_eq!(
+        fs::read_to_string(project_path.join("glue.toml")).unwrap(),
+        glue_before
+    );
+    assert_eq!(
+        fs::read_to_string(&workspace_path).unwrap(),
+        format!("{}[[[broken\n", workspace)
+    );
+    assert!(project_path.join("vendor/foo/lib.rs").exists());
+    assert!(project_path.join("notes.txt").exists());
+
+    fs::write(&workspace_path, &workspace).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .assert()
+        .success();
+    fs::write(
+        project_path.join("app-stm32/src/main.rs"),
+        "// hand edits\n",
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["add-platform", "stm32", "--target", "thumbv7m-none-eabi"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pass --force"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "stm32",
+            "--target",
+            "thumbv7m-none-eabi",
+            "--force",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".multi-target-rs/backups/"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert_eq!(glue.matches("name = \"stm32\"").count(), 1);
+    assert!(glue.contains("target = \"thumbv7m-none-eabi\""));
+    let workspace = fs::read_to_string(&workspace_path).unwrap();
+    assert_eq!(workspace.matches("\"app-stm32\"").count(), 1);
+
+    let backups: Vec<_> = fs::read_dir(project_path.join(".multi-target-rs/backups"))
+        .unwrap()
+        .collect();
+    assert_eq!(backups.len(), 1);
+    let backup = backups[0].as_ref().unwrap().path();
+    assert_eq!(
+        fs::read_to_string(backup.join("app-stm32/src/main.rs")).unwrap(),
+        "// hand edits\n"
+    );
+}
+
+/// Failures exit with a status per class: configuration 2, hardware 5
+#[test]
+fn test_exit_codes() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "nope"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Error: Platform 'nope' not found"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+            "--hal",
+            "nb",
+            "--runner",
+            "probe-rs run --chip STM32F411RETx",
+        ])
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(&glue_path, format!("{}[[[broken\n", glue)).unwrap();
+    for args in [&["list-platforms"][..], &["glue", "validate"][..]] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path).args(args).assert().code(2);
+    }
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk"])
+        .assert()
+        .success();
+
+    // Stand-in for probe-rs with nothing plugged in
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        "#!/bin/sh\necho 'Error: no probe was found' >&2\nexit 1\n",
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .args(["deploy", "--platform", "desk"])
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains("1 of 1 device(s) failed to flash"));
+}
+
+/// Unreachable registries exit 4, failing host tests 6 and a core-lib that doesn't compile 3
+#[test]
+fn test_exit_codes_network_test_build() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    // Nothing listens on the discard port, so every request fails to connect
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("HTTPS_PROXY", "http://127.0.0.1:9")
+        .env("https_proxy", "http://127.0.0.1:9")
+        .env_remove("NO_PROXY")
+        .env_remove("no_proxy")
+        .args(["glue", "init", "desk", "stm32f4xx-hal"])
+        .assert()
+        .code(4)
+        .stderr(predicate::str::contains(
+            "Could not read 'stm32f4xx-hal' from crates.io or docs.rs",
+        ));
+
+    let lib_path = project_path.join("core-lib/src/lib.rs");
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    fs::write(
+        &lib_path,
+        format!(
+            "{}\n#[test]\nfn fails() {{\n    panic!(\"boom\");\n}}\n",
+            lib
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .code(6)
+        .stderr(predicate::str::contains("Tests failed"));
+
+    fs::write(&lib_path, format!("{}\ncompile_error!(\"broken\");\n", lib)).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("build")
+        .assert()
+        .code(3)
+        .stderr(predicate::str::contains("Build failed"));
+}
+
+/// -q hides progress and cargo's output, -v logs the commands being run
+#[test]
+fn test_verbosity_flags() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .args(["-q", "init", "testproj"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty());
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "-v"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "Running \"cargo\" \"build\" \"--workspace\"",
+        ))
+        .stdout(predicate::str::contains("✅ Build completed successfully!"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "-q"])
+        .assert()
+        .success()
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::is_empty());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("MULTI_TARGET_LOG", "info,cmd=off")
+        .args(["build", "--", "--no-such-flag"])
+        .assert()
+        .code(3)
+        .stdout(predicate::str::contains("🔨 Building core-lib"))
+        .stderr(predicate::str::contains("--no-such-flag").not());
+
+    // -q still shows why a build failed
+    let lib_path = project_path.join("core-lib/src/lib.rs");
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    fs::write(&lib_path, format!("{}\ncompile_error!(\"broken\");\n", lib)).unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["-q", "build"])
+        .assert()
+        .code(3)
+        .stdout(predicate::str::is_empty())
+        .stderr(predicate::str::contains("error: broken"))
+        .stderr(predicate::str::contains("Error: Build failed"));
+    fs::write(&lib_path, lib).unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "-q", "-v"])
+        .assert()
+        .code(2);
+}
+
+/// Without a terminal deploy draws no progress bars, and the flasher's \r-redrawn progress
+/// doesn't hide its final error
+#[test]
+fn test_deploy_progress_fallback() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .arg("init")
+        .arg("testproj")
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+            "--hal",
+            "nb",
+            "--runner",
+            "probe-rs run --chip STM32F411RETx",
+        ])
+        .assert()
+        .success();
+
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk"])
+        .assert()
+        .success();
+
+    // Stand-in for probe-rs that loses the probe half way through programming
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(
+        &fake_probe_rs,
+        "#!/bin/sh\nprintf 'Programming 10%%\\rProgramming 55%%\\r' >&2\necho 'Error: probe disconnected' >&2\nexit 1\n",
+    )
+    .unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .args(["deploy", "--platform", "desk"])
+        .assert()
+        .code(5)
+        .stderr(predicate::str::contains(
+            "❌ bench-1: Error: probe disconnected",
+        ))
+        .stderr(predicate::str::contains("[=").not());
+}
+
+/// --plain output is ASCII only, with status emoji turned into tags
+#[test]
+fn test_plain_output() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    let output = cmd
+        .current_dir(&temp)
+        .args(["init", "testproj", "--plain"])
+        .env("NO_COLOR", "1")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.is_ascii(), "{}", stdout);
+    assert!(stdout.contains("  + Created workspace Cargo.toml\n"));
+    assert!(stdout.contains("[ok] Project 'testproj' initialized successfully!"));
+    assert!(!stdout.contains('\x1b'));
+
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["--plain", "--no-color", "build", "--target", "nope"])
+        .assert()
+        .code(2)
+        .stdout(predicate::str::contains("* Building for platform: nope"))
+        .stderr(predicate::str::contains("Error: Platform 'nope' not found"));
+}
+
+/// Defaults from the user config file fill in author, license, examples and toolchain at init
+#[test]
+fn test_user_config_defaults() {
+    let temp = TempDir::new().unwrap();
+    let config_home = temp.path().join("config");
+
+    for (key, value) in [
+        ("author", "Jane Doe <jane@example.com>"),
+        ("license", "MIT"),
+        ("examples", "none"),
+        ("toolchain", "1.82.0"),
+        ("github_token", "ghp_secret"),
+    ] {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&temp)
+            .env("XDG_CONFIG_HOME", &config_home)
+            .args(["config", "set", key, value])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("ghp_secret").not());
+    }
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["config", "set", "build_tool", "make"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("cargo or cross"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["config", "get", "author"])
+        .assert()
+        .success()
+        .stdout("Jane Doe <jane@example.com>\n");
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["config", "get"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("github_token = ****"))
+        .stdout(predicate::str::contains("ghp_secret").not());
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["init", "testproj"])
+        .assert()
+        .success();
+
+    let project_path = temp.path().join("testproj");
+    let workspace = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(workspace.contains("authors = [\"Jane Doe <jane@example.com>\"]"));
+    assert!(workspace.contains("license = \"MIT\""));
+    let toolchain = fs::read_to_string(project_path.join("rust-toolchain.toml")).unwrap();
+    assert!(toolchain.contains("channel = \"1.82.0\""));
+    let lib = fs::read_to_string(project_path.join("core-lib/src/lib.rs")).unwrap();
+    assert!(!lib.contains("TemperatureSensor"));
+
+    // Flags still win over the config
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args([
+            "init",
+            "other",
+            "--examples",
+            "led",
+            "--toolchain",
+            "stable",
+        ])
+        .assert()
+        .success();
+    let toolchain = fs::read_to_string(temp.path().join("other/rust-toolchain.toml")).unwrap();
+    assert!(toolchain.contains("channel = \"stable\""));
+}
+
+/// The user config can hold a GitHub token, so it is only ever readable by its owner
+#[cfg(unix)]
+#[test]
+fn test_user_config_private() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let config_home = temp.path().join("config");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", &config_home)
+        .args(["config", "set", "github_token", "ghp_secret"])
+        .assert()
+        .success();
+
+    let config_dir = config_home.join("multi-target-rs");
+    let mode = fs::metadata(config_dir.join("config.toml"))
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert_eq!(fs::read_dir(&config_dir).unwrap().count(), 1);
+}
+
+/// init --license sets the workspace license and writes the matching LICENSE files
+#[test]
+fn test_init_license() {
+    let temp = TempDir::new().unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", temp.path().join("config"))
+        .arg("init")
+        .arg("dual")
+        .assert()
+        .success();
+    let dual = temp.path().join("dual");
+    assert!(fs::read_to_string(dual.join("Cargo.toml"))
+        .unwrap()
+        .contains("license = \"MIT OR Apache-2.0\""));
+    assert!(fs::read_to_string(dual.join("LICENSE-MIT"))
+        .unwrap()
+        .contains("Copyright (c) 20"));
+    assert!(fs::read_to_string(dual.join("LICENSE-APACHE"))
+        .unwrap()
+        .contains("Version 2.0, January 2004"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", temp.path().join("config"))
+        .args(["init", "closed", "--license", "proprietary"])
+        .assert()
+        .success();
+    let closed = temp.path().join("closed");
+    assert!(fs::read_to_string(closed.join("Cargo.toml"))
+        .unwrap()
+        .contains("license = \"LicenseRef-Proprietary\""));
+    assert!(fs::read_to_string(closed.join("LICENSE"))
+        .unwrap()
+        .contains("All rights reserved."));
+    assert!(!closed.join("LICENSE-MIT").exists());
+
+    // The workspace license reaches every crate, and cargo accepts it
+    let output = std::process::Command::new("cargo")
+        .args([
+            "metadata",
+            "--no-deps",
+            "--format-version",
+            "1",
+            "--offline",
+        ])
+        .current_dir(&closed)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    for package in metadata["packages"].as_array().unwrap() {
+        assert_eq!(package["license"], "LicenseRef-Proprietary");
+    }
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", temp.path().join("config"))
+        .args(["config", "set", "license", "GPL-3.0-only"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .env("XDG_CONFIG_HOME", temp.path().join("config"))
+        .args(["init", "copyleft"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "No license text for 'GPL-3.0-only'",
+        ));
+    assert!(fs::read_to_string(temp.path().join("copyleft/Cargo.toml"))
+        .unwrap()
+        .contains("license = \"GPL-3.0-only\""));
+}
+
+/// Generated authors come from git's user.name/user.email unless the user config sets one
+#[test]
+fn test_init_authors_from_git() {
+    let temp = TempDir::new().unwrap();
+    let gitconfig = temp.path().join("gitconfig");
+    fs::write(
+        &gitconfig,
+        "[user]\n\tname = Ada Lovelace\n\temail = ada@example.com\n",
+    )
+    .unwrap();
+    let run = |args: &[&str], gitconfig: &std::path::Path| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&temp)
+            .env("GIT_CONFIG_GLOBAL", gitconfig)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("XDG_CONFIG_HOME", temp.path().join("config"))
+            .args(args)
+            .assert()
+            .success();
+    };
+
+    run(&["init", "from-git", "--license", "MIT"], &gitconfig);
+    let workspace = fs::read_to_string(temp.path().join("from-git/Cargo.toml")).unwrap();
+    assert!(workspace.contains("authors = [\"Ada Lovelace <ada@example.com>\"]"));
+    let license = fs::read_to_string(temp.path().join("from-git/LICENSE")).unwrap();
+    assert!(license.contains(" Ada Lovelace\n"));
+
+    run(&["init", "anonymous"], std::path::Path::new("/dev/null"));
+    let workspace = fs::read_to_string(temp.path().join("anonymous/Cargo.toml")).unwrap();
+    assert!(workspace.contains("authors = []"));
+
+    run(
+        &["config", "set", "author", "Ada (work) <ada@corp.example>"],
+        &gitconfig,
+    );
+    run(&["init", "from-config"], &gitconfig);
+    let workspace = fs::read_to_string(temp.path().join("from-config/Cargo.toml")).unwrap();
+    assert!(workspace.contains("authors = [\"Ada (work) <ada@corp.example>\"]"));
+}
+
+/// init puts the generated project under git with a .gitignore, unless --no-git
+#[test]
+fn test_init_git_repository() {
+    let temp = TempDir::new().unwrap();
+    let init = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&temp)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .arg("init")
+            .args(args)
+            .assert()
+            .success();
+    };
+
+    init(&["versioned"]);
+    let project_path = temp.path().join("versioned");
+    let gitignore = fs::read_to_string(project_path.join(".gitignore")).unwrap();
+    for entry in ["/target", "/artifacts", "/secrets.toml"] {
+        assert!(gitignore.lines().any(|line| line == entry), "{}", entry);
+    }
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(&project_path)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    assert_eq!(git(&["rev-list", "--count", "HEAD"]).trim(), "1");
+    assert!(git(&["status", "--porcelain"]).is_empty());
+    assert!(git(&["ls-files"]).lines().any(|file| file == "glue.toml"));
+
+    init(&["unversioned", "--no-git"]);
+    assert!(!temp.path().join("unversioned/.git").exists());
+    assert!(temp.path().join("unversioned/.gitignore").exists());
+}
+
+/// hooks install writes git hooks that run the [hooks] commands and block a failing commit
+#[test]
+fn test_hooks_install() {
+    let temp = TempDir::new().unwrap();
+    let project_path = temp.path().join("testproj");
+    let bin = assert_cmd::cargo::cargo_bin("multi-target-rs");
+    let path = std::env::join_paths(
+        std::iter::once(bin.parent().unwrap().to_path_buf())
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .current_dir(&project_path)
+            .env("PATH", &path)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .unwrap()
+    };
+    let install = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .args(["hooks", "install"])
+            .args(args)
+            .assert()
+            .success()
+    };
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--no-git"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["hooks", "install"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("git init"));
+    assert!(git(&["init", "--quiet"]).status.success());
+
+    install(&[])
+        .stdout(predicate::str::contains("pre-commit hook: check --all"))
+        .stdout(predicate::str::contains("pre-push hook: test"));
+    let hook = fs::read_to_string(project_path.join(".git/hooks/pre-commit")).unwrap();
+    assert!(hook.contains("multi-target-rs check --all"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    fs::write(
+        project_path.join("glue.toml"),
+        format!(
+            "{}\n[hooks]\npre-commit = [\"list-platforms\"]\npre-push = []\n",
+            glue
+        ),
+    )
+    .unwrap();
+    install(&[]);
+    assert!(!project_path.join(".git/hooks/pre-push").exists());
+    assert!(git(&["add", "-A"]).status.success());
+    assert!(git(&["commit", "--quiet", "-m", "Initial commit"])
+        .status
+        .success());
+
+    fs::write(
+        project_path.join("glue.toml"),
+        format!(
+            "{}\n[hooks]\npre-commit = [\"check --target nonexistent\"]\n",
+            glue
+        ),
+    )
+    .unwrap();
+    install(&[]);
+    assert!(git(&["add", "-A"]).status.success());
+    let commit = git(&["commit", "--quiet", "-m", "Break the hook"]);
+    assert!(!commit.status.success());
+    assert!(String::from_utf8_lossy(&commit.stderr).contains("nonexistent"));
+
+    fs::write(project_path.join(".git/hooks/pre-commit"), "#!/bin/sh\n").unwrap();
+    install(&[]).stderr(predicate::str::contains("--force"));
+    assert_eq!(
+        fs::read_to_string(project_path.join(".git/hooks/pre-commit")).unwrap(),
+        "#!/bin/sh\n"
+    );
+}
+
+/// report readme keeps the README platform table in sync, also from a post-add-platform hook
+#[test]
+fn test_report_readme() {
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--no-git"])
+        .assert()
+        .success();
+    let project_path = temp.path().join("testproj");
+    let readme = fs::read_to_string(project_path.join("README.md")).unwrap();
+    assert!(readme.contains("No platforms yet"));
+
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    fs::write(
+        project_path.join("glue.toml"),
+        format!(
+            "{}\n[hooks]\npost-add-platform = [\"report readme\"]\n",
+            glue
+        ),
+    )
+    .unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Updated the platform table"));
+    let readme = fs::read_to_string(project_path.join("README.md")).unwrap();
+    assert!(readme.contains("| desk | `x86_64-unknown-linux-gnu` |"));
+    assert!(!readme.contains("No platforms yet"));
+    assert!(readme.starts_with("# testproj\n"));
+
+    // A README without the markers gets the section appended
+    fs::write(project_path.join("README.md"), "# Handwritten\n").unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["report", "readme"])
+        .assert()
+        .success();
+    let readme = fs::read_to_string(project_path.join("README.md")).unwrap();
+    assert!(readme.starts_with("# Handwritten\n\n## Platforms\n\n<!-- platforms:"));
+    assert!(readme.contains("| desk |"));
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["report", "readme"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("up to date"));
+}
+
+/// status reports each platform's last build, flash and test run
+#[test]
+#[cfg(unix)]
+fn test_status() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--no-git"])
+        .assert()
+        .success();
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+            "--hal",
+            "nb",
+            "--runner",
+            "probe-rs run --chip STM32F411RETx",
+        ])
+        .assert()
+        .success();
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Host tests: never run"))
+        .stdout(predicate::str::contains("Build: not built"))
+        .stdout(predicate::str::contains("Flash: never flashed"));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["build", "--target", "desk", "--release"])
+        .assert()
+        .success();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .success();
+
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(&fake_probe_rs, "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .env("PATH", path)
+        .args(["deploy", "--platform", "desk", "--release"])
+        .assert()
+        .success();
+
+    let manifest: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(project_path.join("artifacts/desk/release/manifest.json")).unwrap(),
+    )
+    .unwrap();
+    let sha256 = manifest["artifacts"][0]["sha256"].as_str().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Host tests: ✅ passed ("))
+        .stdout(predicate::str::contains("Build: release, "))
+        .stdout(predicate::str::contains(format!(
+            "Flash: bench-1, firmware {}, just now",
+            &sha256[..12]
+        )))
+        .stdout(predicate::str::contains("Test: never run"));
+}
+
+/// The state store keeps artifact hashes, per-device flash history and test counts
+#[test]
+#[cfg(unix)]
+fn test_state_store() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--no-git"])
+        .assert()
+        .success();
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args([
+            "add-platform",
+            "desk",
+            "--target",
+            "x86_64-unknown-linux-gnu",
+            "--hal",
+            "nb",
+            "--runner",
+            "probe-rs run --chip STM32F411RETx",
+        ])
+        .assert()
+        .success();
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        format!(
+            "{}\n[[devices]]\nname = \"bench-1\"\nplatform = \"desk\"\nprobe = \"0483:374b:0001\"\n",
+            glue
+        ),
+    )
+    .unwrap();
+
+    let bin_dir = temp.path().join("bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    let fake_probe_rs = bin_dir.join("probe-rs");
+    fs::write(&fake_probe_rs, "#!/bin/sh\nexit 0\n").unwrap();
+    fs::set_permissions(&fake_probe_rs, fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    for _ in 0..2 {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .args(["build", "--target", "desk"])
+            .assert()
+            .success();
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .env("PATH", &path)
+            .args(["deploy", "--platform", "desk"])
+            .assert()
+            .success();
+    }
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("test")
+        .assert()
+        .success();
+
+    let state: serde_json::Value = serde_json::from_str(
+        &fs::read_to_string(project_path.join(".multi-target-rs/state.json")).unwrap(),
+    )
+    .unwrap();
+    let build = &state["platforms"]["desk"]["build"];
+    assert_eq!(build["artifacts"][0]["sha256"].as_str().unwrap().len(), 64);
+    let flashes = state["flashes"]["bench-1"].as_array().unwrap();
+    assert_eq!(flashes.len(), 2);
+    assert_eq!(flashes[1]["sha256"], build["artifacts"][0]["sha256"]);
+    assert!(state["host_test"]["total"].as_u64().unwrap() > 0);
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("KiB (+0.0 KiB)"))
+        .stdout(predicate::str::is_match(r"Host tests: ✅ passed \(\d+ tests\)").unwrap());
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["devices", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Last flash: desk firmware"))
+        .stdout(predicate::str::contains("(2 recorded)"));
+}
+
+/// version bump moves every crate, inherited workspace versions and FIRMWARE_VERSION together
+#[test]
+fn test_version_bump() {
+    let temp = TempDir::new().unwrap();
+    let project_path = temp.path().join("testproj");
+    let git_env = [
+        ("GIT_AUTHOR_NAME", "Test"),
+        ("GIT_AUTHOR_EMAIL", "test@example.com"),
+        ("GIT_COMMITTER_NAME", "Test"),
+        ("GIT_COMMITTER_EMAIL", "test@example.com"),
+    ];
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .envs(git_env)
+        .args(["init", "testproj"])
+        .assert()
+        .success();
+    let git = |args: &[&str]| {
+        let output = std::process::Command::new("git")
+            .args(args)
+            .current_dir(&project_path)
+            .envs(git_env)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    };
+    git(&["tag", "v0.1.0"]);
+    let lib_path = project_path.join("core-lib/src/lib.rs");
+    let lib = fs::read_to_string(&lib_path).unwrap();
+    fs::write(
+        &lib_path,
+        format!("{}\npub const FIRMWARE_VERSION: &str = \"0.1.0\";\n", lib),
+    )
+    .unwrap();
+    git(&[
+        "commit",
+        "--quiet",
+        "--all",
+        "-m",
+        "Report the firmware version",
+    ]);
+
+    let bump = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .args(["version", "bump"])
+            .args(args)
+            .assert()
+    };
+    bump(&["minor", "--changelog"])
+        .success()
+        .stdout(predicate::str::contains("Version 0.1.0 → 0.2.0"));
+    let core_manifest = fs::read_to_string(project_path.join("core-lib/Cargo.toml")).unwrap();
+    assert!(core_manifest.contains("version = \"0.2.0\""));
+    assert!(fs::read_to_string(&lib_path)
+        .unwrap()
+        .contains("FIRMWARE_VERSION: &str = \"0.2.0\""));
+    let changelog = fs::read_to_string(project_path.join("CHANGELOG.md")).unwrap();
+    assert!(changelog.starts_with("# Changelog\n\n## [0.2.0] - "));
+    assert!(changelog.contains("\n- Report the firmware version\n"));
+
+    // core-lib inherits its version from the workspace from here on
+    fs::write(
+        project_path.join("core-lib/Cargo.toml"),
+        core_manifest.replace("version = \"0.2.0\"", "version.workspace = true"),
+    )
+    .unwrap();
+    let root = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    fs::write(
+        project_path.join("Cargo.toml"),
+        root.replace(
+            "[workspace.package]\n",
+            "[workspace.package]\nversion = \"0.2.0\"\n",
+        ),
+    )
+    .unwrap();
+    bump(&["patch"])
+        .success()
+        .stdout(predicate::str::contains("Version 0.2.0 → 0.2.1"));
+    let root = fs::read_to_string(project_path.join("Cargo.toml")).unwrap();
+    assert!(root.contains("[workspace.package]\nversion = \"0.2.1\""));
+    assert!(fs::read_to_string(project_path.join("core-lib/Cargo.toml"))
+        .unwrap()
+        .contains("version.workspace = true"));
+    assert!(fs::read_to_string(project_path.join("tests/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"0.2.1\""));
+
+    bump(&["sideways"])
+        .failure()
+        .stderr(predicate::str::contains("not major, minor, patch"));
+}
+
+/// Test that a bump moves members' version requirements on each other along with them
+#[test]
+fn test_version_bump_path_dependencies() {
+    let temp = TempDir::new().unwrap();
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--lib-only", "--no-git"])
+        .assert()
+        .success();
+    let tool = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path).args(args).assert()
+    };
+    tool(&["new-driver", "bme280"]).success();
+
+    // As `publish` leaves it after publishing the driver
+    let manifest_path = project_path.join("drivers/bme280/Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(
+        &manifest_path,
+        manifest.replace(
+            "[dependencies]\n",
+            "[dependencies]\ncore-lib = { path = \"../../core-lib\", version = \"0.1.0\" }\n",
+        ),
+    )
+    .unwrap();
+    std::process::Command::new("cargo")
+        .arg("generate-lockfile")
+        .current_dir(&project_path)
+        .status()
+        .unwrap();
+
+    tool(&["version", "bump", "major"])
+        .success()
+        .stdout(predicate::str::contains("Version 0.1.0 → 1.0.0"))
+        .stdout(predicate::str::contains("Updated Cargo.lock"));
+    assert!(fs::read_to_string(&manifest_path)
+        .unwrap()
+        .contains("core-lib = { path = \"../../core-lib\", version = \"1.0.0\" }"));
+    assert!(fs::read_to_string(project_path.join("core-lib/Cargo.toml"))
+        .unwrap()
+        .contains("version = \"1.0.0\""));
+}
+
+/// Test that publish checks metadata, the no_std build and path dependency versions
+#[test]
+fn test_publish() {
+    let temp = TempDir::new().unwrap();
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--lib-only", "--no-git"])
+        .assert()
+        .success();
+
+    let publish = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path)
+            .arg("publish")
+            .args(args)
+            .args(["--dry-run", "--target", "x86_64-unknown-linux-gnu"])
+            .assert()
+    };
+    publish(&["core-lib"])
+        .success()
+        .stdout(predicate::str::contains(
+            "core-lib is ready to publish (dry run, nothing uploaded)",
+        ));
+
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&project_path)
+        .args(["new-driver", "bme280"])
+        .assert()
+        .success();
+    publish(&["drivers/bme280"])
+        .code(2)
+        .stderr(predicate::str::contains("without description"));
+
+    let manifest_path = project_path.join("drivers/bme280/Cargo.toml");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    fs::write(
+        &manifest_path,
+        manifest
+            .replace(
+                "license.workspace = true\n",
+                "license.workspace = true\ndescription = \"BME280 driver\"\n",
+            )
+            .replace(
+                "[dependencies]\n",
+                "[dependencies]\ncore-lib = { path = \"../../core-lib\" }\n",
+            ),
+    )
+    .unwrap();
+    // Packaging needs core-lib on the registry, so only the rewrite is checked, and the failed
+    // dry run puts the manifest back
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    publish(&["drivers/bme280"])
+        .failure()
+        .stdout(predicate::str::contains(
+            "Added version requirements to bme280's path dependencies",
+        ));
+    assert_eq!(fs::read_to_string(&manifest_path).unwrap(), manifest);
+
+    // The same dependency written as its own table
+    fs::write(
+        &manifest_path,
+        manifest.replace("core-lib = { path = \"../../core-lib\" }\n", "")
+            + "\n[dependencies.core-lib]\npath = \"../../core-lib\"\n",
+    )
+    .unwrap();
+    publish(&["drivers/bme280"])
+        .failure()
+        .stdout(predicate::str::contains(
+            "Added version requirements to bme280's path dependencies",
+        ))
+        .stderr(predicate::str::contains("Couldn't add").not());
+
+    publish(&["app-x"])
+        .code(2)
+        .stderr(predicate::str::contains("Can only publish core-lib"));
+}
+
+/// Test that build and test accept --product for one of several products
+#[test]
+fn test_products() {
+    let temp = TempDir::new().unwrap();
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--lib-only", "--no-git"])
+        .assert()
+        .success();
+    let tool = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path).args(args).assert()
+    };
+
+    tool(&["add-platform", "gw", "--simulator", "--product", "gateway"]).success();
+    tool(&[
+        "test",
+        "--product",
+        "gateway",
+        "--report",
+        "junit:target/junit.xml",
+    ])
+    .success();
+    assert!(project_path.join("target/junit-host.xml").exists());
+    assert!(project_path.join("target/junit-gw.xml").exists());
+
+    tool(&[
+        "add-platform",
+        "stm32",
+        "--target",
+        "thumbv7em-none-eabihf",
+        "--product",
+        "sensor-node",
+    ])
+    .success();
+    let glue = fs::read_to_string(project_path.join("glue.toml")).unwrap();
+    assert!(glue.contains("[[products]]\nname = \"gateway\"\nplatforms = [\"gw\"]"));
+    assert!(glue.contains("[[products]]\nname = \"sensor-node\"\nplatforms = [\"stm32\"]"));
+    tool(&["list-platforms"])
+        .success()
+        .stdout(predicate::str::contains("Product: sensor-node"));
+    tool(&["build", "--product", "sensor-node"])
+        .success()
+        .stdout(predicate::str::contains(
+            "Built 1 platform(s) of product 'sensor-node'",
+        ));
+
+    tool(&["build", "--product", "watch"])
+        .code(2)
+        .stderr(predicate::str::contains(
+            "Product 'watch' not found (known: gateway, sensor-node)",
+        ));
+}
+
+/// Test that drivers/ crates are wired into apps, the workspace and validation
+#[test]
+fn test_drivers() {
+    let temp = TempDir::new().unwrap();
+    let project_path = temp.path().join("testproj");
+    let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+    cmd.current_dir(&temp)
+        .args(["init", "testproj", "--lib-only", "--no-git"])
+        .assert()
+        .success();
+    let tool = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin("multi-target-rs").unwrap();
+        cmd.current_dir(&project_path).args(args).assert()
+    };
+
+    tool(&["new-driver", "bme280"]).success();
+    tool(&["add-platform", "gw", "--simulator", "--driver", "bme280"]).success();
+    assert!(fs::read_to_string(project_path.join("app-gw/Cargo.toml"))
+        .unwrap()
+        .contains("bme280 = { path = \"../drivers/bme280\" }"));
+    tool(&["add-platform", "stm32", "--target", "thumbv7em-none-eabihf"])
+        .success()
+        .stdout(predicate::str::contains("--driver <name,...>"));
+    tool(&[
+        "add-platform",
+        "nrf",
+        "--target",
+        "thumbv7em-none-eabihf",
+        "--driver",
+        "bmp",
+    ])
+    .code(2)
+    .stderr(predicate::str::contains(
+        "No driver 'bmp' in drivers/ (available: bme280)",
+    ));
+
+    // A driver written by hand, outside the workspace
+    let extra = project_path.join("drivers/status-led");
+    fs::create_dir_all(extra.join("src")).unwrap();
+    fs::write(
+        extra.join("Cargo.toml"),
+        "[package]\nname = \"status-led\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nembedded-hal = { workspace = true }\n",
+    )
+    .unwrap();
+    fs::write(
+        extra.join("src/lib.rs"),
+        "#![no_std]\nuse embedded_hal::digital::OutputPin;\n\npub struct StatusLed<P>(P);\n\nimpl<P: OutputPin> StatusLed<P> {\n    pub fn on(&mut self) {\n        let _ = self.0.set_high();\n    }\n}\n",
+    )
+    .unwrap();
+
+    // stm32's HAL was inspected and only provides I2c
+    let glue_path = project_path.join("glue.toml");
+    let glue = fs::read_to_string(&glue_path).unwrap();
+    fs::write(
+        &glue_path,
+        glue + r#"
+[platforms.hal_info]
+source = "stm32f4xx-hal"
+required_traits = []
+mocked_traits = ["I2c"]
+warnings = []
+
+[[platforms.hal_info.provided_traits]]
+name = "I2c"
+module = "i2c"
+implemented_types = ["I2c"]
+native_mockable = true
+"#,
+    )
+    .unwrap();
+    tool(&["glue", "validate"])
+        .success()
+        .stdout(predicate::str::contains("Driver 'bme280' uses I2c"))
+        .stdout(predicate::str::contains(
+            "Driver 'status-led' uses OutputPin",
+        ))
+        .stderr(predicate::str::contains(
+            "stm32's HAL doesn't provide OutputPin",
+        ))
+        .stderr(predicate::str::contains("Not a workspace member"));
+
+    tool(&["glue", "sync"])
+        .success()
+        .stdout(predicate::str::contains(
+            "Added drivers/status-led to the workspace",
+        ));
+    assert!(fs::read_to_string(project_path.join("Cargo.toml"))
+        .unwrap()
+        .contains("\"drivers/status-led\""));
+}